@@ -0,0 +1,273 @@
+//! Keeps track of which argument the next directive will use.
+use quote::quote;
+use syn::Expr;
+
+/// The arguments available to a sequence of directives.
+#[derive(Clone)]
+pub(crate) enum Arguments<'a> {
+    /// The arguments passed to the macro. These are known statically, so we can
+    /// move freely among them.
+    Listed {
+        expressions: &'a [Expr],
+        cursor: usize,
+        /// `~@{` is iterating over these arguments.
+        iterating: bool,
+        /// Inside `~:@{`, these are the fields of an argument, and this is whether it is the
+        /// last argument.
+        last_sublist: Option<bool>,
+        /// The number of iteration loops these arguments are used in.
+        loops: usize,
+        /// The index of the pass of the innermost iteration, for `~#`.
+        pass: Option<proc_macro2::TokenStream>,
+    },
+    /// Every argument is produced by the same expression at runtime, for example
+    /// the next element of an iteration.
+    ///
+    /// The expression is written out once for each argument a directive uses, so inside
+    /// `~{` each directive advances the iterator and `~^` between them peeks at the element
+    /// the next directive would use.
+    Indexed {
+        expr: &'a Expr,
+        remaining: proc_macro2::TokenStream,
+        loops: usize,
+        pass: Option<proc_macro2::TokenStream>,
+    },
+}
+
+impl<'a> Arguments<'a> {
+    pub(crate) fn listed(expressions: &'a [Expr]) -> Self {
+        Self::Listed {
+            expressions,
+            cursor: 0,
+            iterating: false,
+            last_sublist: None,
+            loops: 0,
+            pass: None,
+        }
+    }
+
+    /// The fields of an argument to `~:@{`, which stops at `~^` if it is the `last` argument.
+    pub(crate) fn sublist(fields: &'a [Expr], last: bool) -> Self {
+        Self::Listed {
+            expressions: fields,
+            cursor: 0,
+            iterating: false,
+            last_sublist: Some(last),
+            loops: 0,
+            pass: None,
+        }
+    }
+
+    pub(crate) fn indexed(expr: &'a Expr, remaining: proc_macro2::TokenStream) -> Self {
+        Self::Indexed {
+            expr,
+            remaining,
+            loops: 0,
+            pass: None,
+        }
+    }
+
+    /// Sets the number of iteration loops the arguments are used in.
+    pub(crate) fn within(mut self, count: usize) -> Self {
+        match &mut self {
+            Self::Listed { loops, .. } | Self::Indexed { loops, .. } => *loops = count,
+        }
+        self
+    }
+
+    /// The number of iteration loops the arguments are used in.
+    pub(crate) fn loops(&self) -> usize {
+        match self {
+            Self::Listed { loops, .. } | Self::Indexed { loops, .. } => *loops,
+        }
+    }
+
+    /// Sets the tokens for the index of the current pass, returning the previous ones.
+    pub(crate) fn set_pass(
+        &mut self,
+        index: Option<proc_macro2::TokenStream>,
+    ) -> Option<proc_macro2::TokenStream> {
+        match self {
+            Self::Listed { pass, .. } | Self::Indexed { pass, .. } => {
+                std::mem::replace(pass, index)
+            }
+        }
+    }
+
+    /// Uses the index of the pass from `outer`, for arguments nested inside its directives.
+    pub(crate) fn in_pass_of(mut self, outer: &Arguments<'_>) -> Self {
+        match outer {
+            Arguments::Listed { pass, .. } | Arguments::Indexed { pass, .. } => {
+                self.set_pass(pass.clone());
+            }
+        }
+        self
+    }
+
+    /// Returns the tokens for `~#`, the index of the current pass.
+    pub(crate) fn pass(&self) -> Result<proc_macro2::TokenStream, String> {
+        match self {
+            Self::Listed { pass, .. } | Self::Indexed { pass, .. } => pass
+                .clone()
+                .ok_or_else(|| "`~#` must be inside an iteration".to_string()),
+        }
+    }
+
+    /// Sets whether `~@{` is iterating over the arguments, returning the previous setting.
+    pub(crate) fn set_iterating(&mut self, value: bool) -> Result<bool, String> {
+        match self {
+            Self::Listed { iterating, .. } => Ok(std::mem::replace(iterating, value)),
+            Self::Indexed { .. } => Err("`~@{` is not supported inside this directive".to_string()),
+        }
+    }
+
+    /// The number of arguments left to use, if it is known when the macro is expanded.
+    pub(crate) fn remaining_count(&self) -> Option<usize> {
+        match self {
+            Self::Listed {
+                expressions,
+                cursor,
+                ..
+            } => Some(expressions.len().saturating_sub(*cursor)),
+            Self::Indexed { .. } => None,
+        }
+    }
+
+    /// Returns the tokens for `~^`, which stops the iteration if there are no arguments left.
+    ///
+    /// With `outer`, `~:^` stops the enclosing iteration if it has no elements left. A
+    /// `condition` from the directive's parameters replaces the check for what is left.
+    pub(crate) fn break_if_done(
+        &self,
+        outer: bool,
+        condition: Option<proc_macro2::TokenStream>,
+    ) -> Result<proc_macro2::TokenStream, String> {
+        let (done, target) = match self {
+            Self::Listed {
+                iterating: true, ..
+            } if outer => return Err("`~:^` is not supported inside `~@{`".to_string()),
+            Self::Listed {
+                last_sublist: Some(_),
+                ..
+            } if outer => return Err("`~:^` is not supported inside `~:@{`".to_string()),
+            // Whether the argument is the last is known too.
+            Self::Listed {
+                last_sublist: Some(last),
+                ..
+            } => match &condition {
+                Some(_) => (quote!(), quote!('__formatcl_remaining)),
+                // Breaking inside `if` keeps the directives after it reachable.
+                None if *last => (quote!(true), quote!('__formatcl_remaining)),
+                None => return Ok(quote!()),
+            },
+            // The listed arguments are known, so we know now whether to stop.
+            Self::Listed {
+                iterating: true, ..
+            } => match &condition {
+                Some(_) => (quote!(), quote!('__formatcl_remaining)),
+                None if self.remaining_count() == Some(0) => {
+                    (quote!(true), quote!('__formatcl_remaining))
+                }
+                None => return Ok(quote!()),
+            },
+            // The fields of a `~:{` sublist are enclosed by the loop over the sublists.
+            Self::Listed { loops: 1.., .. } if outer => {
+                (quote!(__formatcl_iteration.peek().is_none()), quote!())
+            }
+            Self::Indexed { loops: 2.., .. } if outer => {
+                let label = iteration_label(self.loops() - 1);
+                (quote!(__formatcl_outer_done), quote!(#label))
+            }
+            Self::Listed { .. } | Self::Indexed { .. } if outer => {
+                return Err("`~:^` must be inside a nested iteration".to_string())
+            }
+            Self::Listed { .. } | Self::Indexed { .. } => {
+                (quote!(__formatcl_iteration.peek().is_none()), quote!())
+            }
+        };
+
+        let done = condition.unwrap_or(done);
+        Ok(quote! {
+            if #done {
+                break #target;
+            }
+        })
+    }
+
+    /// Returns the tokens for the number of arguments remaining.
+    pub(crate) fn remaining(&self) -> proc_macro2::TokenStream {
+        match self {
+            Self::Listed {
+                expressions,
+                cursor,
+                ..
+            } => {
+                let remaining = expressions.len().saturating_sub(*cursor);
+                quote!(#remaining)
+            }
+            Self::Indexed { remaining, .. } => remaining.clone(),
+        }
+    }
+
+    /// Moves the cursor back `count` arguments so they are used again.
+    pub(crate) fn backup(&mut self, count: usize) -> Result<(), String> {
+        match self {
+            Self::Listed { cursor, .. } => {
+                *cursor = cursor.checked_sub(count).ok_or_else(|| match count {
+                    1 => "cannot back up 1 argument with `~:*`".to_string(),
+                    count => format!("cannot back up {count} arguments with `~:*`"),
+                })?;
+                Ok(())
+            }
+            Self::Indexed { .. } => Err("`~:*` is not supported inside this directive".to_string()),
+        }
+    }
+
+    /// Moves the cursor so the next argument used is the one at `index`.
+    pub(crate) fn goto(&mut self, index: usize) -> Result<(), String> {
+        match self {
+            Self::Listed {
+                expressions,
+                cursor,
+                ..
+            } => {
+                if index > expressions.len() {
+                    return Err(format!(
+                        "cannot jump to argument {index} with `~@*`, only {} provided",
+                        expressions.len()
+                    ));
+                }
+                *cursor = index;
+                Ok(())
+            }
+            Self::Indexed { .. } => Err("`~@*` is not supported inside this directive".to_string()),
+        }
+    }
+}
+
+/// The label of the loop for the iteration nested `depth` loops deep.
+pub(crate) fn iteration_label(depth: usize) -> syn::Lifetime {
+    syn::Lifetime::new(
+        &format!("'__formatcl_iteration_{depth}"),
+        proc_macro2::Span::call_site(),
+    )
+}
+
+impl<'a> Iterator for Arguments<'a> {
+    type Item = &'a Expr;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Self::Listed {
+                expressions,
+                cursor,
+                ..
+            } => {
+                let expr = expressions.get(*cursor)?;
+                *cursor += 1;
+                Some(expr)
+            }
+            Self::Indexed { expr, .. } => Some(expr),
+        }
+    }
+}
@@ -0,0 +1,68 @@
+//! Defines a macro for a format string that is used in several places.
+use quote::{quote, ToTokens};
+use syn::{
+    parse::{Parse, ParseStream},
+    token::Comma,
+    Expr, Ident, LitStr,
+};
+
+use crate::format_input::parse_literal;
+
+/// `clformat_compile!(name, "format string")` - defines `name!`, which takes a destination
+/// and arguments and formats them with the format string, as `clformat!` does.
+pub(crate) struct CompiledFormat {
+    name: Ident,
+    /// `locale = expr` and the other options, passed on to `clformat!`.
+    options: Vec<(Ident, Expr)>,
+    formatlit: LitStr,
+}
+
+impl Parse for CompiledFormat {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let name: Ident = input.parse()?;
+        let _: Comma = input.parse()?;
+
+        let mut options = Vec::new();
+        while input.peek(Ident) && input.peek2(syn::Token![=]) {
+            let option: Ident = input.parse()?;
+            let _: syn::Token![=] = input.parse()?;
+            let value: Expr = input.parse()?;
+            let _: Comma = input.parse()?;
+            options.push((option, value));
+        }
+
+        // The format string is checked here, so that mistakes are reported once where it is
+        // written rather than at each use.
+        let formatlit: LitStr = input.parse()?;
+        parse_literal(&formatlit)?;
+        if !input.is_empty() {
+            let _: Comma = input.parse()?;
+        }
+
+        Ok(Self {
+            name,
+            options,
+            formatlit,
+        })
+    }
+}
+
+impl ToTokens for CompiledFormat {
+    fn to_tokens(&self, tokens: &mut proc_macro2::TokenStream) {
+        let name = &self.name;
+        let formatlit = &self.formatlit;
+        let options = self
+            .options
+            .iter()
+            .map(|(option, value)| quote!(#option = #value,));
+
+        quote! {
+            macro_rules! #name {
+                ($destination:expr $(, $argument:expr)* $(,)?) => {
+                    ::clformat::clformat!($destination, #(#options)* #formatlit $(, $argument)*)
+                };
+            }
+        }
+        .to_tokens(tokens)
+    }
+}
@@ -1,6 +1,7 @@
-use quote::quote;
 use quote::ToTokens;
+use quote::{format_ident, quote, quote_spanned};
 use syn::parse_quote;
+use syn::spanned::Spanned;
 use syn::{
     parse::{Parse, ParseStream},
     punctuated::Punctuated,
@@ -8,19 +9,37 @@ use syn::{
     Expr, LitStr,
 };
 
-use crate::parse::Alignment;
-use crate::parse::{parse_format_string, Directive};
+use clformat_parse::{parse_format_string, Directive, Navigation, Padding, Value};
+
+use crate::arguments::{iteration_label, Arguments};
 
 enum Output {
+    /// A `core::fmt::Write`, or a byte buffer that implements `Extend<u8>`.
     Writer(Expr),
+    /// `io!(writer)` - a `std::io::Write`.
+    Io(Expr),
     String,
+    /// `&mut buffer as string` - clears and reuses a `String` rather than allocating one.
+    Buffer(Expr),
     Stdout,
+    /// `clformat_args!` - a value that implements `Display`, writing to the `Formatter`.
+    Display,
 }
 
 pub(crate) struct FormatInput {
+    /// The format string as written, for error messages.
+    format_string: String,
+    /// Where the format string is, for errors found while writing out the directives.
+    format_span: proc_macro2::Span,
     formatstr: Vec<Directive>,
     output: Output,
+    /// `locale = expr` - the `clformat::Locale` for the numbers.
+    locale: Option<Expr>,
+    /// `dyn_args = true` - format `~A` and `~S` arguments through trait objects.
+    dyn_args: bool,
     expressions: Punctuated<Expr, Comma>,
+    /// Evaluate to a `Result` rather than panicking if a write fails.
+    fallible: bool,
 }
 
 impl std::fmt::Debug for FormatInput {
@@ -32,146 +51,1126 @@ impl std::fmt::Debug for FormatInput {
 impl Parse for FormatInput {
     fn parse(input: ParseStream) -> syn::Result<Self> {
         let output: Expr = input.parse()?;
-        let output = match output {
+        let output = match ungroup(output) {
             Expr::Path(path) if path.path.is_ident("nil") => Output::String,
             Expr::Path(path) if path.path.is_ident("t") => Output::Stdout,
+            Expr::Macro(mac) if mac.mac.path.is_ident("io") => Output::Io(mac.mac.parse_body()?),
+            Expr::Cast(cast) if is_ident(&cast.ty, "string") => Output::Buffer(*cast.expr),
             expr => Output::Writer(expr),
         };
         let _: Comma = input.parse().expect("parse comma");
 
+        Self::parse_format(output, input)
+    }
+}
+
+impl FormatInput {
+    /// Parses the input to `clformat_args!`, which has no destination.
+    pub(crate) fn parse_display(input: ParseStream) -> syn::Result<Self> {
+        Self::parse_format(Output::Display, input)
+    }
+
+    /// Parses the format string and the arguments that follow the destination.
+    fn parse_format(output: Output, input: ParseStream) -> syn::Result<Self> {
+        let mut locale = None;
+        let mut dyn_args = false;
+        while input.peek(syn::Ident) && input.peek2(syn::Token![=]) {
+            let name: syn::Ident = input.parse()?;
+            let _: syn::Token![=] = input.parse()?;
+            if name == "locale" {
+                locale = Some(input.parse::<Expr>()?);
+            } else if name == "dyn_args" {
+                dyn_args = input.parse::<syn::LitBool>()?.value;
+            } else {
+                return Err(syn::Error::new_spanned(
+                    name,
+                    "expected `locale`, `dyn_args` or the format string",
+                ));
+            }
+            let _: Comma = input.parse()?;
+        }
+
         let formatlit: LitStr = input.parse()?;
-        let s = formatlit.value().clone();
-        let formatstr = parse_format_string(formatlit, &s)?;
+        let format_string = formatlit.value();
+        let formatstr = parse_literal(&formatlit)?;
 
-        let _: Comma = input.parse().expect("parse comma");
-        let expressions = Punctuated::<Expr, Comma>::parse_terminated(input)?;
+        // The arguments can be omitted if the format string only uses captured variables.
+        if !input.is_empty() {
+            let _: Comma = input.parse()?;
+        }
+        // Arguments passed on by a `macro_rules!` macro, such as one defined by
+        // `clformat_compile!`, are wrapped in invisible groups.
+        let expressions = Punctuated::<Expr, Comma>::parse_terminated(input)?
+            .into_iter()
+            .map(ungroup)
+            .collect::<Punctuated<Expr, Comma>>();
+
+        let required = required_arguments(&formatstr, &mut 0, expressions.len());
+        if required > expressions.len() {
+            let provided = match expressions.len() {
+                1 => "1 was".to_string(),
+                provided => format!("{provided} were"),
+            };
+            let expected = match required {
+                1 => "1 argument".to_string(),
+                required => format!("{required} arguments"),
+            };
+            return Err(syn::Error::new_spanned(
+                formatlit,
+                format!("format string expects {expected} but {provided} provided"),
+            ));
+        }
+
+        // As with `format!`, every argument must be used.
+        if let Some(unused) = expressions
+            .iter()
+            .skip(required)
+            .map(|expr| syn::Error::new_spanned(expr, "argument never used by the format string"))
+            .reduce(|mut errors, error| {
+                errors.combine(error);
+                errors
+            })
+        {
+            return Err(unused);
+        }
 
         Ok(Self {
+            format_string,
+            format_span: formatlit.span(),
             formatstr,
             output,
+            locale,
+            dyn_args,
             expressions,
+            fallible: false,
         })
     }
+
+    pub(crate) fn fallible(self) -> Self {
+        Self {
+            fallible: true,
+            ..self
+        }
+    }
+
+    /// Writes a newline after the directives, as `writeln!` does.
+    pub(crate) fn with_newline(mut self) -> Self {
+        self.formatstr.push(Directive::Newline {
+            count: Value::Literal(1),
+            crlf: false,
+        });
+        self
+    }
 }
 
 impl ToTokens for FormatInput {
     fn to_tokens(&self, tokens: &mut proc_macro2::TokenStream) {
-        let mut expressions = self.expressions.iter();
+        // Every argument is evaluated once, in order, before anything is written, and the
+        // directives use the bindings. Variables and other places are borrowed, so the caller
+        // keeps them, while other values are bound as they are, so `~{` can take iterators.
+        // `clformat_args!` moves the values into the closure that writes them, which borrows
+        // them each time it runs, so they are used as places too.
+        let display = matches!(self.output, Output::Display);
+        let mut borrows = Vec::new();
+        let (values, bindings): (Vec<_>, Vec<_>) = self
+            .expressions
+            .iter()
+            .enumerate()
+            .map(|(idx, expression)| {
+                let span = expression
+                    .span()
+                    .resolved_at(proc_macro2::Span::call_site());
+                let binding = format_ident!("{}{}", ARGUMENT_PREFIX, idx, span = span);
+                // Spanned so that errors about the argument still point at it.
+                let place = || {
+                    syn::parse2(quote_spanned!(span=> (*#binding)))
+                        .expect("a dereference is an expression")
+                };
+                if is_place(expression) {
+                    (quote!(&#expression), (binding.clone(), place()))
+                } else if display {
+                    borrows.push(quote!(let #binding = &#binding;));
+                    (quote!(#expression), (binding.clone(), place()))
+                } else {
+                    (
+                        quote!(#expression),
+                        (binding.clone(), parse_quote!(#binding)),
+                    )
+                }
+            })
+            .unzip();
+        let (bindings, expressions): (Vec<_>, Vec<Expr>) = bindings.into_iter().unzip();
+        let mut expressions = Arguments::listed(&expressions);
 
         let var_name: Expr = parse_quote!(__formatcl_result);
 
+        // The writer is only wrapped to track the column when a directive needs it.
+        let tracks_column = uses_column(&self.formatstr);
+        let writer: Expr = if tracks_column {
+            parse_quote!(__formatcl_col)
+        } else {
+            var_name.clone()
+        };
+
         let mut expr_tokens = proc_macro2::TokenStream::new();
-        write_expressions(
+        if let Err(err) = write_expressions(
             &mut expressions,
             &self.formatstr,
             &mut expr_tokens,
-            var_name.clone(),
-        );
+            writer.clone(),
+            self.dyn_args,
+        ) {
+            syn::Error::new(self.format_span, err)
+                .to_compile_error()
+                .to_tokens(tokens);
+            return;
+        }
+        let track_column = tracks_column.then(|| {
+            quote! {
+                let mut #writer = ::clformat::ColumnWriter::new(&mut #var_name);
+            }
+        });
 
-        let uses = match self.output {
-            Output::String => {
+        // Numbers are formatted with the locale's characters unless the directive gives them.
+        let locale = match &self.locale {
+            Some(locale) => Some(quote! {
+                #[allow(unused_variables)]
+                let __formatcl_locale: ::clformat::Locale = #locale;
+            }),
+            None => uses_locale(&self.formatstr).then(|| {
                 quote! {
-                    use ::std::fmt::Write;
-                    let mut #var_name = String::new();
+                    let __formatcl_locale = ::clformat::Locale::DEFAULT;
                 }
-            }
-            Output::Stdout => {
+            }),
+        };
+
+        // `io::Write` writers are wrapped so that everything is written with `fmt::Write`.
+        let (uses, value) = match self.output {
+            Output::String => (
+                quote! {
+                    let mut #var_name = ::clformat::__private::String::new();
+                },
+                quote!(#var_name),
+            ),
+            Output::Buffer(ref expr) => (
                 quote! {
-                    use ::std::io::Write;
-                    let mut #var_name = ::std::io::stdout();
+                    #[allow(unused_mut)]
+                    let mut #var_name: &mut ::clformat::__private::String = #expr;
+                    #var_name.clear();
+                },
+                quote!(#var_name.as_str()),
+            ),
+            Output::Stdout => (
+                quote! {
+                    let mut #var_name = ::clformat::IoWriter::new(::std::io::stdout());
+                },
+                quote!(#var_name.into_inner()),
+            ),
+            // Resolves to the writer itself if it implements `fmt::Write`, otherwise a byte
+            // buffer is wrapped in a `ByteWriter`.
+            Output::Writer(ref expr) => (
+                {
+                    // Errors about the destination point at it.
+                    let span = expr.span().resolved_at(proc_macro2::Span::call_site());
+                    let writer_call = quote_spanned!(span=> (&mut #expr).clformat_writer());
+                    quote! {
+                    #[allow(unused_imports)]
+                    use ::clformat::__private::{BytesKind as _, FmtWriterKind as _, OtherKind as _};
+                    let mut #var_name = #writer_call;
+                    }
+                },
+                quote!(#var_name),
+            ),
+            Output::Io(ref expr) => (
+                quote! {
+                    let mut #var_name = ::clformat::IoWriter::new(&mut #expr);
+                },
+                quote!(#var_name.into_inner()),
+            ),
+            Output::Display => (
+                quote! {
+                    #[allow(unused_mut)]
+                    let mut #var_name = __formatcl_formatter;
+                },
+                quote!(),
+            ),
+        };
+
+        // The runtime formatter's error from the body of `~{~}` is reported rather than the
+        // `fmt::Error` it is passed out as.
+        let runtime = uses_runtime_iteration(&self.formatstr);
+        let runtime_error = runtime.then(|| {
+            quote! {
+                let mut __formatcl_runtime_error = ::core::option::Option::None;
+            }
+        });
+
+        let error = match self.output {
+            Output::String | Output::Buffer(_) | Output::Writer(_) | Output::Display => {
+                quote!(err)
+            }
+            Output::Stdout | Output::Io(_) if runtime => quote! {
+                __formatcl_runtime_error
+                    .map_or_else(|| #var_name.into_error(), ::std::io::Error::other)
+            },
+            Output::Stdout | Output::Io(_) => quote!(#var_name.into_error()),
+        };
+
+        let result = if let Output::Display = self.output {
+            // Errors are passed on to whatever is writing the value.
+            quote!(__formatcl_err)
+        } else if self.fallible {
+            quote! {
+                match __formatcl_err {
+                    Ok(()) => Ok(#value),
+                    Err(err) => Err(#error),
                 }
             }
-            Output::Writer(ref expr) => {
+        } else {
+            let format_string = &self.format_string;
+            let runtime_panic = runtime.then(|| {
                 quote! {
-                    let mut #var_name = &mut #expr;
+                    if let ::core::option::Option::Some(err) = __formatcl_runtime_error {
+                        panic!(
+                            "clformat: the body of `~{{~}}` failed for format string {:?} at {}:{}:{}: {}",
+                            #format_string,
+                            ::core::file!(),
+                            ::core::line!(),
+                            ::core::column!(),
+                            err,
+                        );
+                    }
                 }
+            });
+            quote! {
+                // The location is the call of the macro, so a failed write can be traced to it.
+                if let Err(err) = __formatcl_err {
+                    #runtime_panic
+                    panic!(
+                        "clformat: write failed for format string {:?} at {}:{}:{}: {}",
+                        #format_string,
+                        ::core::file!(),
+                        ::core::line!(),
+                        ::core::column!(),
+                        #error,
+                    );
+                }
+
+                #value
             }
         };
 
-        quote! {
+        let setup = quote! {
+            use ::core::fmt::Write as _;
             #uses
-            let __formatcl_err: Result<(), _> = '__format_cl__loop: loop {
+            #track_column
+            #locale
+            #runtime_error
+        };
+        let write = quote! {
+            '__format_cl__loop: loop {
                 #expr_tokens
                 break '__format_cl__loop Ok(());
-            };
+            }
+        };
+
+        if display {
+            // The arguments are evaluated once, as with `format_args!`, and the directives are
+            // written each time the value is formatted.
+            quote! {
+                struct __FormatclArgs<F>(F);
+
+                impl<F> ::core::fmt::Display for __FormatclArgs<F>
+                where
+                    F: Fn(&mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result,
+                {
+                    fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+                        (self.0)(f)
+                    }
+                }
+
+                match (#(#values,)*) {
+                    #[allow(unused_variables)]
+                    (#(#bindings,)*) => __FormatclArgs(
+                        move |__formatcl_formatter: &mut ::core::fmt::Formatter<'_>| -> ::core::fmt::Result {
+                            #(#borrows)*
+                            #setup
+                            let __formatcl_err: ::core::fmt::Result = #write;
 
-            if __formatcl_err.is_err() {
-                panic!("oh no");
+                            #result
+                        },
+                    ),
+                }
             }
+            .to_tokens(tokens);
+        } else {
+            quote! {
+                #setup
+                // Temporaries in the arguments live until the end of the `match`, as they
+                // would in `format_args!`.
+                let __formatcl_err: ::core::fmt::Result = match (#(#values,)*) {
+                    #[allow(unused_variables)]
+                    (#(#bindings,)*) => #write,
+                };
 
-            #var_name
+                #result
+            }
+            .to_tokens(tokens);
         }
-        .to_tokens(tokens);
     }
 }
 
-fn write_expressions<'a, T>(
-    expressions: &mut T,
+/// Parses the format string, reporting errors at the directive that caused them.
+pub(crate) fn parse_literal(formatlit: &LitStr) -> syn::Result<Vec<Directive>> {
+    let format_string = formatlit.value();
+    parse_format_string(&format_string).map_err(|err| {
+        match error_span(formatlit, &format_string, err.position) {
+            Some(span) => syn::Error::new(span, err),
+            None => syn::Error::new_spanned(formatlit, err),
+        }
+    })
+}
+
+/// The name of the bindings for the arguments, followed by the index of the argument.
+const ARGUMENT_PREFIX: &str = "__formatcl_arg_";
+
+/// Whether the expression is a place, such as a variable or a field, that is borrowed rather
+/// than moved when the argument is bound.
+fn is_place(expression: &Expr) -> bool {
+    match expression {
+        Expr::Path(_) | Expr::Field(_) | Expr::Index(_) => true,
+        Expr::Unary(unary) => matches!(unary.op, syn::UnOp::Deref(_)),
+        Expr::Paren(paren) => is_place(&paren.expr),
+        _ => false,
+    }
+}
+
+/// Whether the expression is the binding of an argument that isn't a place, which `~{` can
+/// take by value.
+fn is_bound_value(expression: &Expr) -> bool {
+    match expression {
+        Expr::Path(path) => path
+            .path
+            .get_ident()
+            .is_some_and(|ident| ident.to_string().starts_with(ARGUMENT_PREFIX)),
+        _ => false,
+    }
+}
+
+/// Removes the invisible group around an expression passed through `macro_rules!`, so that
+/// `nil` and variables are recognised.
+fn ungroup(expr: Expr) -> Expr {
+    match expr {
+        Expr::Group(group) => ungroup(*group.expr),
+        expr => expr,
+    }
+}
+
+fn is_ident(ty: &syn::Type, ident: &str) -> bool {
+    matches!(ty, syn::Type::Path(path) if path.qself.is_none() && path.path.is_ident(ident))
+}
+
+/// The span of the directive at `position` in the format string, so that errors underline
+/// just that directive. This is only available when the compiler supports subspans and the
+/// literal has no escapes that would move the directive.
+fn error_span(
+    formatlit: &LitStr,
+    format_string: &str,
+    position: usize,
+) -> Option<proc_macro2::Span> {
+    let source = formatlit.token().to_string();
+    let hashes = source
+        .strip_prefix('r')
+        .map_or(0, |raw| raw.chars().take_while(|&c| c == '#').count());
+    let start = source.find('"')? + 1;
+    let end = source.len().checked_sub(hashes + 1)?;
+    if source.get(start..end)? != format_string {
+        return None;
+    }
+
+    let len = directive_len(format_string.get(position..)?);
+    formatlit
+        .token()
+        .subspan(start + position..start + position + len)
+}
+
+/// The length in bytes of the directive at the start of `input`: the `~`, its parameters,
+/// modifiers and the directive character.
+fn directive_len(input: &str) -> usize {
+    let mut chars = input.char_indices().skip(1);
+    while let Some((idx, c)) = chars.next() {
+        match c {
+            '0'..='9' | ',' | '+' | '-' | 'V' | 'v' | '#' | ':' | '@' => {}
+            // The character after a quote is a parameter, whatever it is.
+            '\'' => {
+                chars.next();
+            }
+            '`' => {
+                chars.find(|&(_, c)| c == '`');
+            }
+            _ => return idx + c.len_utf8(),
+        }
+    }
+    input.len()
+}
+
+/// Whether `predicate` holds for any of the directives, or for any directive nested in them.
+fn any_directive(directives: &[Directive], predicate: &impl Fn(&Directive) -> bool) -> bool {
+    directives.iter().any(|directive| {
+        predicate(directive)
+            || match directive {
+                Directive::Captured { directive, .. } => {
+                    any_directive(std::slice::from_ref(directive), predicate)
+                }
+                Directive::Align {
+                    segments, overflow, ..
+                } => segments
+                    .iter()
+                    .chain(overflow.as_ref().map(|overflow| &overflow.directives))
+                    .any(|segment| any_directive(segment, predicate)),
+                Directive::Iteration {
+                    directives, empty, ..
+                } => directives
+                    .iter()
+                    .chain(empty.iter().flatten())
+                    .any(|directive| any_directive(std::slice::from_ref(directive), predicate)),
+                Directive::Conditional {
+                    choices, default, ..
+                } => choices
+                    .iter()
+                    .chain(default)
+                    .any(|choice| any_directive(choice, predicate)),
+                _ => false,
+            }
+    })
+}
+
+/// Whether any of the directives format numbers with the characters from the locale.
+fn uses_locale(directives: &[Directive]) -> bool {
+    any_directive(directives, &|directive| match directive {
+        Directive::Decimal {
+            comma_char,
+            comma_interval,
+            radix,
+            ..
+        } => *radix == 10 || comma_char.is_none() || comma_interval.is_none(),
+        Directive::Float { .. } => true,
+        _ => false,
+    })
+}
+
+/// Whether any of the directives take the body of `~{~}` from an argument.
+fn uses_runtime_iteration(directives: &[Directive]) -> bool {
+    any_directive(
+        directives,
+        &|directive| matches!(directive, Directive::Iteration { remaining: false, directives, .. } if directives.is_empty()),
+    )
+}
+
+/// Whether any of the directives need to know the column the output has reached.
+fn uses_column(directives: &[Directive]) -> bool {
+    any_directive(directives, &|directive| {
+        matches!(
+            directive,
+            Directive::Align {
+                overflow: Some(_),
+                ..
+            }
+        )
+    })
+}
+
+/// Returns the number of macro arguments the directives use, starting from `cursor`, when
+/// `provided` arguments are given. Directives inside iterations and `~@[` use the elements
+/// of their argument, so they aren't counted.
+fn required_arguments(directives: &[Directive], cursor: &mut usize, provided: usize) -> usize {
+    let mut required = *cursor;
+
+    for directive in directives {
+        match directive {
+            Directive::TildeA { padding, .. } | Directive::TildeS { padding, .. } => {
+                *cursor += from_arg(&padding.min_columns)
+                    + from_arg(&padding.col_inc)
+                    + from_arg(&padding.min_pad)
+                    + from_arg(&padding.pad_char)
+                    + padding.max_columns.as_ref().map_or(0, from_arg)
+                    + 1;
+            }
+            Directive::Decimal {
+                min_columns,
+                pad_char,
+                comma_char,
+                comma_interval,
+                secondary_interval,
+                ..
+            } => {
+                *cursor += from_arg(min_columns)
+                    + from_arg(pad_char)
+                    + comma_char.as_ref().map_or(0, from_arg)
+                    + secondary_interval.as_ref().map_or(0, from_arg)
+                    + comma_interval.as_ref().map_or(0, from_arg)
+                    + 1;
+            }
+            Directive::Float {
+                width,
+                num_decimal_places,
+                ..
+            } => *cursor += from_arg(width) + num_decimal_places.as_ref().map_or(0, from_arg) + 1,
+            Directive::Align {
+                min_columns,
+                pad_char,
+                segments,
+                overflow,
+                ..
+            } => {
+                *cursor += from_arg(min_columns) + from_arg(pad_char);
+                let overflow = overflow.iter().map(|overflow| &overflow.directives);
+                for segment in overflow.chain(segments) {
+                    required = required.max(required_arguments(segment, cursor, provided));
+                }
+            }
+            Directive::Iteration {
+                sublists: true,
+                remaining: true,
+                max_passes,
+                empty,
+                ..
+            } => {
+                required = required.max(empty_arguments(empty, *cursor, provided));
+                // Each pass uses the next argument, its fields are used by the directives.
+                let mut passes = 0;
+                while *cursor < provided && Some(passes) != *max_passes {
+                    passes += 1;
+                    *cursor += 1;
+                }
+            }
+            Directive::Iteration {
+                sublists: false,
+                remaining: true,
+                max_passes,
+                directives,
+                empty,
+            } => {
+                required = required.max(empty_arguments(empty, *cursor, provided));
+                // Each pass uses the next arguments until they run out, or `~^` stops it.
+                let mut passes = 0;
+                while *cursor < provided && Some(passes) != *max_passes {
+                    passes += 1;
+                    let start = *cursor;
+                    for directive in directives {
+                        if is_plain_break(directive) && *cursor >= provided {
+                            break;
+                        }
+                        required = required.max(required_arguments(
+                            std::slice::from_ref(directive),
+                            cursor,
+                            provided,
+                        ));
+                    }
+                    // Each pass uses at least one argument.
+                    *cursor = (*cursor).max(start + 1);
+                }
+            }
+            // `~{~}` takes the body as an argument before the list.
+            Directive::Iteration { directives, .. } if directives.is_empty() => *cursor += 2,
+            Directive::Iteration { empty, .. } => {
+                *cursor += 1;
+                if let Some(empty) = empty {
+                    required =
+                        required.max(required_arguments(empty, &mut cursor.clone(), provided));
+                }
+            }
+            Directive::Write { .. } => *cursor += 1,
+            Directive::Conditional { consumes: true, .. } => *cursor += 1,
+            Directive::Conditional {
+                consumes: false,
+                selector,
+                choices,
+                default,
+                ..
+            } => {
+                let remaining = provided.saturating_sub(*cursor);
+                if let Some(index) = selected_choice(selector, Some(remaining)) {
+                    // Only the selected choice is written.
+                    if let Some(choice) = choices.get(index).or(default.as_ref()) {
+                        required =
+                            required.max(required_arguments(choice, &mut cursor.clone(), provided));
+                    }
+                } else {
+                    *cursor += selector.as_ref().map_or(1, from_arg);
+                    // Each choice starts from the same argument.
+                    for choice in choices.iter().chain(default) {
+                        required =
+                            required.max(required_arguments(choice, &mut cursor.clone(), provided));
+                    }
+                }
+            }
+            // Invalid moves are reported when the code is generated.
+            Directive::ArgNav { count, direction } => match direction {
+                Navigation::Forward => *cursor += count,
+                Navigation::Backward => *cursor = cursor.saturating_sub(*count),
+                Navigation::Absolute => *cursor = *count,
+            },
+            Directive::Newline { count, .. } => *cursor += from_arg(count),
+            Directive::Captured { .. } | Directive::Literal(_) | Directive::LoopIndex => (),
+            Directive::Break { params, .. } => {
+                *cursor += params.iter().map(from_arg).sum::<usize>()
+            }
+        }
+
+        required = required.max(*cursor);
+    }
+
+    required
+}
+
+/// The number of arguments the empty part of `~@{body~:;empty~}` needs, starting from
+/// `cursor`. It is only written when no arguments remain.
+fn empty_arguments(empty: &Option<Vec<Directive>>, cursor: usize, provided: usize) -> usize {
+    match empty {
+        Some(empty) if cursor >= provided => {
+            required_arguments(empty, &mut cursor.clone(), provided)
+        }
+        _ => 0,
+    }
+}
+
+/// The index of the choice `~[` selects, if it is known when the macro is expanded.
+fn selected_choice(selector: &Option<Value<usize>>, remaining: Option<usize>) -> Option<usize> {
+    match selector {
+        Some(Value::Literal(index)) => Some(*index),
+        Some(Value::ArgCount) => remaining,
+        Some(Value::FromArg) | None => None,
+    }
+}
+
+/// Whether the directive is a `~^` that stops once the arguments run out.
+fn is_plain_break(directive: &Directive) -> bool {
+    matches!(directive, Directive::Break { outer: false, params } if params.is_empty())
+}
+
+/// The number of arguments a parameter takes.
+fn from_arg<T>(value: &Value<T>) -> usize {
+    usize::from(matches!(value, Value::FromArg))
+}
+
+fn write_expressions(
+    expressions: &mut Arguments,
     directives: &[Directive],
     tokens: &mut proc_macro2::TokenStream,
     writer: Expr,
-) where
-    T: Iterator<Item = &'a Expr> + Clone,
-{
+    dyn_args: bool,
+) -> Result<(), String> {
     for directive in directives {
         match directive {
-            Directive::TildeA => {
-                let expression = expressions.next().expect("enough parameters");
+            Directive::TildeA {
+                padding,
+                print_nil,
+                separator,
+                alternate,
+            } => {
+                let convert = if *print_nil {
+                    // Resolves to `ClDisplay` if the argument implements it, otherwise `Display`.
+                    let display = match separator {
+                        Some(separator) => quote!(cl_joined(#separator)),
+                        None => quote!(cl_display()),
+                    };
+                    quote! {
+                        #[allow(unused_imports)]
+                        use ::clformat::__private::{ClDisplayKind as _, DisplayKind as _};
+                        let __formatcl_arg = __formatcl_arg.#display;
+                    }
+                } else {
+                    quote!()
+                };
+                write_padded(
+                    expressions,
+                    padding,
+                    if *alternate { "{:#}" } else { "{}" },
+                    convert,
+                    tokens,
+                    &writer,
+                    dyn_args,
+                )
+            }
+            Directive::TildeS { padding, alternate } => write_padded(
+                expressions,
+                padding,
+                if *alternate { "{:#?}" } else { "{:?}" },
+                quote!(),
+                tokens,
+                &writer,
+                dyn_args,
+            ),
+            Directive::Write { pretty } => {
+                let spec = if *pretty { "{:#?}" } else { "{:?}" };
+                write_padded(
+                    expressions,
+                    &Padding::default(),
+                    spec,
+                    quote!(),
+                    tokens,
+                    &writer,
+                    dyn_args,
+                )
+            }
+            Directive::Newline {
+                count: Value::Literal(count),
+                crlf,
+            } => {
+                let newlines = newline(*crlf).repeat(*count);
                 quote! {
-                     let r = write!(#writer, "{}", #expression);
-                     if r.is_err() {
+                    let r = write!(#writer, "{}", #newlines);
+                    if r.is_err() {
                         break '__format_cl__loop r;
                     }
                 }
                 .to_tokens(tokens)
             }
-            Directive::TildeS => {
-                let expression = expressions.next().expect("enough parameters");
+            Directive::Newline { count, crlf } => {
+                let count = num_value(count, expressions);
+                let newline = newline(*crlf);
                 quote! {
-                    let r = write!(#writer, "{:?}", #expression);
-                    if r.is_err() {
-                        break '__format_cl__loop r;
+                    for _ in 0..#count {
+                        let r = write!(#writer, "{}", #newline);
+                        if r.is_err() {
+                            break '__format_cl__loop r;
+                        }
                     }
                 }
                 .to_tokens(tokens)
             }
-            Directive::Newline => quote! {
-               let r = write!(#writer, "\n");
-               if r.is_err() {
-                   break '__format_cl__loop r;
-               }
-            }
-            .to_tokens(tokens),
+            // The literal is an argument rather than the format string so that braces are
+            // written verbatim. `format_args!` inlines string literal arguments, so this is as
+            // cheap as writing the string directly.
             Directive::Literal(literal) => quote! {
-               let r = write!(#writer, #literal);
+               let r = write!(#writer, "{}", #literal);
                if r.is_err() {
                    break '__format_cl__loop r;
                }
             }
             .to_tokens(tokens),
-            Directive::Skip => {
-                let expression = expressions.next().expect("enough parameters");
-                // Note we have to output the expression since loop expressions involve side effects.
-                quote! {  let _ = #expression; }.to_tokens(tokens)
-            }
-            Directive::Iteration(directives) => {
-                let expression = expressions.next().expect("enough parameters");
-                let iter = syn::parse_str::<Expr>("__formatcl_iteration.next().unwrap()")
-                    .expect("static string should be valid syntax");
-                let mut nested = IndexedExpression {
-                    count: 0,
-                    expr: &iter,
+            Directive::LoopIndex => {
+                let index = expressions.pass()?;
+                quote! {
+                    let r = write!(#writer, "{}", #index);
+                    if r.is_err() {
+                        break '__format_cl__loop r;
+                    }
+                }
+                .to_tokens(tokens)
+            }
+            Directive::ArgNav {
+                count,
+                direction: Navigation::Backward,
+            } => expressions.backup(*count)?,
+            Directive::ArgNav {
+                count,
+                direction: Navigation::Absolute,
+            } => expressions.goto(*count)?,
+            Directive::ArgNav {
+                count,
+                direction: Navigation::Forward,
+            } => {
+                for _ in 0..*count {
+                    let expression = expressions
+                        .next()
+                        .expect("argument count checked when parsing");
+                    // The macro arguments have already been evaluated, but inside `~{` this
+                    // advances past the element.
+                    quote! {  let _ = #expression; }.to_tokens(tokens)
+                }
+            }
+            Directive::Captured { name, directive } => {
+                // The directive takes its argument from the captured variable. The parser
+                // rejects `V` parameters, so nothing else is taken from the arguments.
+                let captured = syn::parse_str::<Expr>(name)
+                    .expect("the parser only accepts identifiers and field accesses");
+                let mut captured_arguments = Arguments::indexed(&captured, expressions.remaining())
+                    .within(expressions.loops());
+                write_expressions(
+                    &mut captured_arguments,
+                    std::slice::from_ref(directive),
+                    tokens,
+                    writer.clone(),
+                    dyn_args,
+                )?;
+            }
+            Directive::Iteration {
+                sublists: true,
+                remaining: true,
+                max_passes,
+                directives,
+                empty,
+            } => {
+                if expressions.remaining_count() == Some(0) {
+                    write_empty(expressions, empty, tokens, &writer, dyn_args)?;
+                }
+                // Each remaining argument is a tuple, written out in turn as a pass over its
+                // fields.
+                let fields = (0..required_arguments(directives, &mut 0, usize::MAX))
+                    .map(|field| {
+                        let field = syn::Index::from(field);
+                        parse_quote!(__formatcl_sublist.#field)
+                    })
+                    .collect::<Vec<Expr>>();
+                let outer = expressions.set_iterating(true)?;
+                let mut passes = proc_macro2::TokenStream::new();
+                let mut count = 0;
+                while let Some(1..) = expressions.remaining_count() {
+                    if Some(count) == *max_passes {
+                        break;
+                    }
+                    count += 1;
+                    let expression = expressions
+                        .next()
+                        .expect("argument count checked when parsing");
+                    let last = expressions.remaining_count() == Some(0);
+                    let mut nested = Arguments::sublist(&fields, last).within(expressions.loops());
+                    let pass = count - 1;
+                    nested.set_pass(Some(quote!(#pass)));
+                    let mut block = proc_macro2::TokenStream::new();
+                    for directive in directives {
+                        if is_plain_break(directive) {
+                            if last {
+                                break;
+                            }
+                            continue;
+                        }
+                        write_expressions(
+                            &mut nested,
+                            std::slice::from_ref(directive),
+                            &mut block,
+                            writer.clone(),
+                            dyn_args,
+                        )?;
+                    }
+                    quote! {
+                        {
+                            #[allow(unused_variables)]
+                            let __formatcl_sublist = &#expression;
+                            #block
+                        }
+                    }
+                    .to_tokens(&mut passes);
+                }
+                let _ = expressions.set_iterating(outer);
+
+                quote! {
+                    '__formatcl_remaining: {
+                        #passes
+                    }
+                }
+                .to_tokens(tokens);
+            }
+            Directive::Iteration {
+                sublists: false,
+                remaining: true,
+                max_passes,
+                directives,
+                empty,
+            } => {
+                if expressions.remaining_count() == Some(0) {
+                    write_empty(expressions, empty, tokens, &writer, dyn_args)?;
+                }
+                // The remaining arguments are known, so each pass is written out in turn.
+                let outer = expressions.set_iterating(true)?;
+                let outer_pass = expressions.set_pass(None);
+                let mut passes = proc_macro2::TokenStream::new();
+                let mut count = 0;
+                'passes: while let Some(start @ 1..) = expressions.remaining_count() {
+                    if Some(count) == *max_passes {
+                        break;
+                    }
+                    expressions.set_pass(Some(quote!(#count)));
+                    count += 1;
+                    for directive in directives {
+                        if is_plain_break(directive) && expressions.remaining_count() == Some(0) {
+                            break 'passes;
+                        }
+                        write_expressions(
+                            expressions,
+                            std::slice::from_ref(directive),
+                            &mut passes,
+                            writer.clone(),
+                            dyn_args,
+                        )?;
+                    }
+                    if expressions.remaining_count() >= Some(start) {
+                        // A pass that uses no arguments would repeat forever, so skip one.
+                        let expression = expressions
+                            .next()
+                            .expect("argument count checked when parsing");
+                        quote! { let _ = #expression; }.to_tokens(&mut passes);
+                    }
+                }
+                let _ = expressions.set_iterating(outer);
+                expressions.set_pass(outer_pass);
+
+                quote! {
+                    '__formatcl_remaining: {
+                        #passes
+                    }
+                }
+                .to_tokens(tokens);
+            }
+            Directive::Iteration {
+                max_passes,
+                directives,
+                ..
+            } if directives.is_empty() => {
+                // The body is only known at runtime, so the runtime formatter is used.
+                let body = expressions
+                    .next()
+                    .expect("argument count checked when parsing");
+                let list = expressions
+                    .next()
+                    .expect("argument count checked when parsing");
+                let list = borrow_list(list);
+                // The runtime formatter takes the body from the argument before the list too.
+                let format_string = match max_passes {
+                    Some(max_passes) => format!("~{max_passes}{{~}}"),
+                    None => "~{~}".to_string(),
                 };
+
+                // The error from the body is kept so its message can be reported.
+                quote! {
+                    let r = ::clformat::__write_iteration!(
+                        &mut #writer,
+                        #format_string,
+                        &#body,
+                        #list,
+                        &mut __formatcl_runtime_error,
+                    );
+                    if r.is_err() {
+                        break '__format_cl__loop r;
+                    }
+                }
+                .to_tokens(tokens);
+            }
+            Directive::Iteration {
+                sublists: false,
+                max_passes,
+                directives,
+                empty,
+                ..
+            } => {
+                let expression = expressions
+                    .next()
+                    .expect("argument count checked when parsing");
+                let empty = check_empty(expressions, empty, &writer, dyn_args)?;
+                // Each directive in the body takes the next element, so a pass can use several.
+                let iter: Expr = parse_quote! {
+                    __formatcl_iteration
+                        .next()
+                        .expect("clformat: the list ran out of elements during a pass of `~{`")
+                };
+                let loops = expressions.loops() + 1;
+                let (pass, count_pass, count_pass_next) = count_passes(directives, loops);
+                let mut nested =
+                    Arguments::indexed(&iter, quote!(__formatcl_iteration.len())).within(loops);
+                nested.set_pass(Some(pass));
                 let mut block = proc_macro2::TokenStream::new();
-                write_expressions(&mut nested, directives, &mut block, writer.clone());
+                write_expressions(
+                    &mut nested,
+                    directives,
+                    &mut block,
+                    writer.clone(),
+                    dyn_args,
+                )?;
+
+                // A body that uses no elements would loop forever, so each pass skips one.
+                let skip = (required_arguments(directives, &mut 0, usize::MAX) == 0)
+                    .then(|| quote!(let _ = __formatcl_iteration.next();));
 
+                // `~:^` needs to know whether the enclosing iteration has finished, which
+                // can't be checked once its iterator is shadowed.
+                let outer_done = (loops > 1).then(|| {
+                    quote! {
+                        #[allow(unused_variables)]
+                        let __formatcl_outer_done = __formatcl_iteration.peek().is_none();
+                    }
+                });
+
+                let label = iteration_label(loops);
+                let (counter, limit) = limit_passes(max_passes);
+                let list = borrow_list(expression);
+                // The block keeps the iterator of an enclosing iteration from being shadowed
+                // for the directives after this one.
                 quote! {
-                    let mut __formatcl_iteration = #expression.into_iter().peekable();
-                    loop {
-                        if __formatcl_iteration.peek().is_none() {
-                            break;
+                    {
+                        let __formatcl_list = #list;
+                        #outer_done
+                        let mut __formatcl_elements = __formatcl_list.into_iter().peekable();
+                        #empty
+                        let mut __formatcl_iteration = __formatcl_elements;
+                        #counter
+                        #count_pass
+                        #[allow(unused_labels)]
+                        #label: loop {
+                            if __formatcl_iteration.peek().is_none() {
+                                break;
+                            }
+                            #limit
+                            { #block }
+                            #skip
+                            #count_pass_next
+                        }
+                    }
+                }
+                .to_tokens(tokens);
+            }
+            Directive::Iteration {
+                sublists: true,
+                max_passes,
+                directives,
+                empty,
+                ..
+            } => {
+                let expression = expressions
+                    .next()
+                    .expect("argument count checked when parsing");
+                let empty = check_empty(expressions, empty, &writer, dyn_args)?;
+                // Each element is a tuple, the directives take their arguments from its fields.
+                let fields = (0..required_arguments(directives, &mut 0, usize::MAX))
+                    .map(|field| {
+                        let field = syn::Index::from(field);
+                        parse_quote!(__formatcl_sublist.#field)
+                    })
+                    .collect::<Vec<Expr>>();
+                let loops = expressions.loops() + 1;
+                let (pass, count_pass, count_pass_next) = count_passes(directives, loops);
+                let mut nested = Arguments::listed(&fields).within(loops);
+                nested.set_pass(Some(pass));
+                let mut block = proc_macro2::TokenStream::new();
+                write_expressions(
+                    &mut nested,
+                    directives,
+                    &mut block,
+                    writer.clone(),
+                    dyn_args,
+                )?;
+
+                let label = iteration_label(loops);
+                let (counter, limit) = limit_passes(max_passes);
+                let list = borrow_list(expression);
+                quote! {
+                    {
+                        let mut __formatcl_elements = (#list).into_iter().peekable();
+                        #empty
+                        let mut __formatcl_iteration = __formatcl_elements;
+                        #counter
+                        #count_pass
+                        #[allow(unused_labels)]
+                        #label: loop {
+                            #limit
+                            let __formatcl_sublist = match __formatcl_iteration.next() {
+                                ::core::option::Option::Some(sublist) => sublist,
+                                ::core::option::Option::None => break,
+                            };
+                            { #block }
+                            #count_pass_next
                         }
-                        { #block }
                     }
                 }
                 .to_tokens(tokens);
@@ -179,10 +1178,13 @@ fn write_expressions<'a, T>(
             Directive::Conditional {
                 boolean: true,
                 consumes: _,
+                selector: _,
                 choices,
                 default: _,
             } => {
-                let expression = expressions.next().expect("enough parameters");
+                let expression = expressions
+                    .next()
+                    .expect("argument count checked when parsing");
 
                 let then = {
                     let mut block = proc_macro2::TokenStream::new();
@@ -192,7 +1194,8 @@ fn write_expressions<'a, T>(
                         &choices[0],
                         &mut block,
                         writer.clone(),
-                    );
+                        dyn_args,
+                    )?;
                     block
                 };
 
@@ -204,12 +1207,16 @@ fn write_expressions<'a, T>(
                         &choices[1],
                         &mut block,
                         writer.clone(),
-                    );
+                        dyn_args,
+                    )?;
                     block
                 };
 
+                let truthy = quote_spanned! {expression.span()=>
+                    ::clformat::Truthy::is_truthy(&(#expression))
+                };
                 quote! {
-                    if #expression {
+                    if #truthy {
                         #then
                     } else {
                         #r#else
@@ -219,96 +1226,172 @@ fn write_expressions<'a, T>(
             }
             Directive::Conditional {
                 boolean: false,
-                consumes,
+                consumes: true,
                 choices,
-                default,
+                ..
             } => {
-                let expression = expressions.next().expect("enough parameters");
+                let expression = expressions
+                    .next()
+                    .expect("argument count checked when parsing");
 
-                if *consumes {
-                    // We should have validated there is only one choice when `consumes` is true.
-                    let choice = &choices[0];
-                    let mut block = proc_macro2::TokenStream::new();
-                    let expr = syn::parse_str::<Expr>("__formatcl_inner")
-                        .expect("static string should be valid syntax");
-                    let mut inner = IndexedExpression {
-                        count: 0,
-                        expr: &expr,
-                    };
+                // We should have validated there is only one choice when `consumes` is true.
+                let choice = &choices[0];
+                let mut block = proc_macro2::TokenStream::new();
+                let expr = syn::parse_str::<Expr>("__formatcl_inner")
+                    .expect("static string should be valid syntax");
+                let mut inner = Arguments::indexed(&expr, quote!(1usize)).in_pass_of(expressions);
 
-                    write_expressions(&mut inner, &choice, &mut block, writer.clone());
+                write_expressions(&mut inner, choice, &mut block, writer.clone(), dyn_args)?;
 
-                    quote! {
-                        if let ::core::option::Option::Some(__formatcl_inner) = #expression {
-                            #block
-                        }
+                // The option is borrowed so the caller can still use it.
+                let option = quote_spanned! {expression.span()=>
+                    ::clformat::__private::option_arg(&(#expression))
+                };
+                quote! {
+                    if let ::core::option::Option::Some(__formatcl_inner) = #option {
+                        #block
                     }
-                    .to_tokens(tokens);
-                } else {
-                    let mut match_tokens = quote! {};
-
-                    for (idx, e) in choices.iter().enumerate() {
+                }
+                .to_tokens(tokens);
+            }
+            Directive::Conditional {
+                boolean: false,
+                consumes: false,
+                selector,
+                choices,
+                default,
+            } => {
+                // The choice can be made now if the selector is known.
+                if let Some(index) = selected_choice(selector, expressions.remaining_count()) {
+                    if let Some(choice) = choices.get(index).or(default.as_ref()) {
                         let mut block = proc_macro2::TokenStream::new();
-                        write_expressions(&mut expressions.clone(), e, &mut block, writer.clone());
-                        match_tokens = quote! {
-                            #match_tokens
-                            #idx => { #block }
-                        };
+                        write_expressions(
+                            &mut expressions.clone(),
+                            choice,
+                            &mut block,
+                            writer.clone(),
+                            dyn_args,
+                        )?;
+                        quote!({ #block }).to_tokens(tokens);
+                    }
+                    continue;
+                }
+
+                // A `V` selector selects as the argument to `~[` does, so negative numbers
+                // select the default.
+                let expression = match selector {
+                    Some(Value::FromArg) | None => {
+                        let expression = expressions
+                            .next()
+                            .expect("argument count checked when parsing");
+                        quote_spanned! {expression.span()=>
+                            ::clformat::Choice::choice(&(#expression))
+                        }
                     }
+                    Some(selector) => num_value(selector, expressions),
+                };
+
+                let mut match_tokens = quote! {};
+
+                for (idx, e) in choices.iter().enumerate() {
+                    let mut block = proc_macro2::TokenStream::new();
+                    write_expressions(
+                        &mut expressions.clone(),
+                        e,
+                        &mut block,
+                        writer.clone(),
+                        dyn_args,
+                    )?;
+                    match_tokens = quote! {
+                        #match_tokens
+                        #idx => { #block }
+                    };
+                }
 
-                    if let Some(default) = default {
+                let default = match default {
+                    Some(default) => {
                         let mut block = proc_macro2::TokenStream::new();
                         write_expressions(
                             &mut expressions.clone(),
                             default,
                             &mut block,
                             writer.clone(),
-                        );
-
-                        match_tokens = quote! {
-                            #match_tokens
-                            _ => { #block }
-                        }
+                            dyn_args,
+                        )?;
+                        block
                     }
+                    None => quote!(),
+                };
 
-                    quote! {
-                        match #expression {
-                            #match_tokens
-                        }
-                    }
-                    .to_tokens(tokens);
-                }
-            }
-            Directive::Break => {
                 quote! {
-                    if __formatcl_iteration.peek().is_none() {
-                        break;
+                    match #expression {
+                        #match_tokens
+                        _ => { #default }
                     }
                 }
                 .to_tokens(tokens);
             }
+            Directive::Break { outer, params } => {
+                let condition = break_condition(params, expressions);
+                expressions
+                    .break_if_done(*outer, condition)?
+                    .to_tokens(tokens)
+            }
             Directive::Decimal {
                 min_columns,
                 pad_char,
                 comma_char,
                 comma_interval,
+                secondary_interval,
                 print_commas,
                 print_sign,
+                radix,
+                prefix,
+                lowercase,
+                pad_after_sign,
             } => {
-                let expression = expressions.next().expect("enough parameters");
+                // Parameters taken from the arguments precede the argument being formatted.
+                let min_columns = num_value(min_columns, expressions);
+                let pad_char = char_value(pad_char, expressions);
+                let comma_char = match comma_char {
+                    Some(comma_char) => char_value(comma_char, expressions),
+                    None => quote!(__formatcl_locale.thousands),
+                };
+                let comma_interval = match comma_interval {
+                    Some(interval) => num_value(interval, expressions),
+                    None => quote!(__formatcl_locale.grouping),
+                };
+                let secondary_interval = secondary_interval.as_ref().map(|interval| {
+                    let interval = num_value(interval, expressions);
+                    quote!(.secondary_interval(#interval))
+                });
+                // Only decimal numbers are written in the locale's numeral system.
+                let zero_digit =
+                    (*radix == 10).then(|| quote!(.zero_digit(__formatcl_locale.zero)));
+                let radix = (*radix != 10).then(|| quote!(.radix(#radix)));
+                let prefix = prefix.then(|| quote!(.rust_prefix()));
+                let lowercase = lowercase.then(|| quote!(.lowercase()));
+                let pad_after_sign = pad_after_sign.then(|| quote!(.pad_after_sign()));
+                let expression = expressions
+                    .next()
+                    .expect("argument count checked when parsing");
+                // The number is borrowed as the other arguments are, with the borrow spanned to
+                // the argument so that the error for a non-number points at it.
+                let number = quote_spanned!(expression.span()=> &#expression);
                 quote! {
-                    for __formatcl_c in ::clformat::Decimal::new(
-                                             #min_columns,
-                                             #pad_char,
-                                             #comma_char,
-                                             #comma_interval,
-                                             #print_commas,
-                                             #print_sign,
-                                             #expression) {
-                        let r = write!(#writer, "{}", __formatcl_c);
-                        if r.is_err() {
-                            break '__format_cl__loop r;
-                        }
+                    let r = ::clformat::Decimal::new(
+                        #min_columns,
+                        #pad_char,
+                        #comma_char,
+                        #comma_interval,
+                        #print_commas,
+                        #print_sign,
+                        #number,
+                    )
+                    #secondary_interval #radix #prefix #lowercase #pad_after_sign #zero_digit
+                    .write_to(&mut #writer);
+                    if r.is_err() {
+                        break '__format_cl__loop r;
                     }
                 }
                 .to_tokens(tokens)
@@ -318,23 +1401,45 @@ fn write_expressions<'a, T>(
                 width,
                 num_decimal_places,
                 pad_char,
+                print_sign,
+                drop_leading_zero,
+                round_half_up,
+                left_justify,
             } => {
-                let expression = expressions.next().expect("enough parameters");
-                let format = format!(
-                    "{{:{pad_char}>{width}.{num_decimal_places}}}",
-                    width = if *width == 0 {
-                        String::new()
-                    } else {
-                        width.to_string()
-                    },
-                    num_decimal_places = if *num_decimal_places == 0 {
-                        String::new()
-                    } else {
-                        num_decimal_places.to_string()
+                let width = num_value(width, expressions);
+                let num_decimal_places = match num_decimal_places {
+                    None => quote!(::core::option::Option::None),
+                    Some(num_decimal_places) => {
+                        let num_decimal_places = num_value(num_decimal_places, expressions);
+                        quote!(::core::option::Option::Some(#num_decimal_places))
                     }
-                );
+                };
+                let drop_leading_zero = drop_leading_zero.then(|| quote!(.drop_leading_zero()));
+                let round_half_up = round_half_up.then(|| quote!(.round_half_up()));
+                let left_justify = left_justify.then(|| quote!(.left_justify()));
+                let expression = expressions
+                    .next()
+                    .expect("argument count checked when parsing");
+                let value = quote_spanned! {expression.span()=>
+                    ::clformat::__private::float_arg(&#expression)
+                };
                 quote! {
-                    let r = write!(#writer, #format, #expression);
+                    let r = write!(
+                        #writer,
+                        "{}",
+                        ::clformat::Float::new(
+                            #width,
+                            #num_decimal_places,
+                            #pad_char,
+                            #print_sign,
+                            #value,
+                        )
+                        .decimal_char(__formatcl_locale.decimal)
+                        .zero_digit(__formatcl_locale.zero)
+                        #drop_leading_zero
+                        #round_half_up
+                        #left_justify,
+                    );
                     if r.is_err() {
                         break '__format_cl__loop r;
                     }
@@ -343,92 +1448,469 @@ fn write_expressions<'a, T>(
             }
             Directive::Align {
                 min_columns,
+                col_inc,
+                min_pad,
                 pad_char,
                 direction,
-                inner,
-                ..
+                segments,
+                overflow,
             } => {
-                // Alignment is achieved by writing the contained tokens twice.
-                // First we write to a ruler writer which measures the length of the text, this is used to
-                // calculate the padding, which we then output before writing the blocks properly.
-                let mut ruler_block = proc_macro2::TokenStream::new();
-                let mut writer_block = proc_macro2::TokenStream::new();
-                let ruler_var: Expr = parse_quote!(__formatcl_ruler);
-                write_expressions(
-                    &mut expressions.clone(),
-                    inner,
-                    &mut ruler_block,
-                    ruler_var.clone(),
-                );
-                write_expressions(expressions, inner, &mut writer_block, writer.clone());
-
-                let fill = format!("{{:{pad_char}<width$}}");
-
-                let left_fill = match direction {
-                    Alignment::Left => Default::default(),
-                    Alignment::Right => quote! {
-                        if #min_columns > #ruler_var.length() {
-                            let r = write!(#writer, #fill, "", width = #min_columns - #ruler_var.length());
-                            if r.is_err() {
-                                break '__format_cl__loop r;
-                            }
-                        }
-                    },
-                    Alignment::Centre => quote! {
-                        if #min_columns > #ruler_var.length() {
-                            let r = write!(#writer, #fill, "", width = (#min_columns - #ruler_var.length()) / 2);
-                            if r.is_err() {
-                                break '__format_cl__loop r;
+                let min_columns = num_value(min_columns, expressions);
+                let pad_char = char_value(pad_char, expressions);
+
+                // The overflow clause is written to a buffer first, since it comes first in
+                // the format string, and written out if the justified text won't fit on the line.
+                let overflow = overflow
+                    .as_ref()
+                    .map(|overflow| -> Result<_, String> {
+                        let buffer: Expr = parse_quote!(__formatcl_overflow);
+                        let mut block = proc_macro2::TokenStream::new();
+                        write_expressions(
+                            expressions,
+                            &overflow.directives,
+                            &mut block,
+                            buffer.clone(),
+                            dyn_args,
+                        )?;
+
+                        let spare = overflow.spare;
+                        let line_width = overflow.line_width;
+                        let written = quote! {
+                            let __formatcl_width = __formatcl_ruler.length() + __formatcl_padding;
+                            if __formatcl_col.column() + __formatcl_width + #spare > #line_width {
+                                let r = #writer.write_str(&#buffer);
+                                if r.is_err() {
+                                    break '__format_cl__loop r;
+                                }
                             }
+                        };
+
+                        Ok((
+                            quote! {
+                                let mut #buffer = ::clformat::__private::String::new();
+                                #block
+                            },
+                            written,
+                        ))
+                    })
+                    .transpose()?;
+                let (overflow_block, overflow_output) = overflow.unzip();
+
+                // Each segment is written to a buffer. The buffers are measured to calculate
+                // the padding, which is then shared out between the gaps around the buffers.
+                let buffers = (0..segments.len())
+                    .map(|idx| format_ident!("__formatcl_segment_{}", idx))
+                    .collect::<Vec<_>>();
+                let mut buffer_blocks = proc_macro2::TokenStream::new();
+                for (segment, buffer) in segments.iter().zip(&buffers) {
+                    write_expressions(
+                        expressions,
+                        segment,
+                        &mut buffer_blocks,
+                        parse_quote!(#buffer),
+                        dyn_args,
+                    )?;
+                }
+
+                let pads_before = direction.pads_before();
+                let pads_after = direction.pads_after(segments.len());
+                let gaps = segments.len() - 1 + usize::from(pads_before) + usize::from(pads_after);
+                // The minimum padding applies to each gap.
+                let min_pad = min_pad * gaps;
+                let mut gap = 0..gaps;
+                let mut next_fill = || {
+                    let gap = gap.next().expect("counted every gap");
+                    // Any remaining padding goes in the first gaps.
+                    let count = if gaps == 1 {
+                        quote!(__formatcl_padding)
+                    } else {
+                        quote! {
+                            __formatcl_padding / #gaps
+                                + usize::from(#gap < __formatcl_padding % #gaps)
                         }
-                    },
+                    };
+                    fill(&writer, count)
                 };
 
-                let right_fill = match direction {
-                    Alignment::Left => quote! {
-                        if #min_columns > #ruler_var.length() {
-                            let r = write!(#writer, #fill, "", width = #min_columns - #ruler_var.length());
-                            if r.is_err() {
-                                break '__format_cl__loop r;
-                            }
-                        }
-                    },
-                    Alignment::Right => Default::default(),
-                    Alignment::Centre => quote! {
-                        if #min_columns > #ruler_var.length() {
-                            let r = write!(#writer, #fill, "", width = (#min_columns - #ruler_var.length()) / 2);
-                            if r.is_err() {
-                                break '__format_cl__loop r;
-                            }
+                let mut output = proc_macro2::TokenStream::new();
+                for (idx, buffer) in buffers.iter().enumerate() {
+                    if idx > 0 || pads_before {
+                        next_fill().to_tokens(&mut output);
+                    }
+                    quote! {
+                        let r = #writer.write_str(&#buffer);
+                        if r.is_err() {
+                            break '__format_cl__loop r;
                         }
-                    },
-                };
+                    }
+                    .to_tokens(&mut output);
+                }
+                if pads_after {
+                    next_fill().to_tokens(&mut output);
+                }
 
+                // The block stops nested alignments shadowing our variables.
                 quote! {
-                    let mut #ruler_var = ::clformat::Ruler::default();
-                    #ruler_block
+                    {
+                        let __formatcl_min_columns: usize = #min_columns;
+                        let __formatcl_pad_char: char = #pad_char;
+                        #overflow_block
+                        #(let mut #buffers = ::clformat::__private::String::new();)*
+                        #buffer_blocks
 
-                    #left_fill
-                    #writer_block
-                    #right_fill
+                        let mut __formatcl_ruler = ::clformat::Ruler::default();
+                        #(let _ = __formatcl_ruler.write_str(&#buffers);)*
+                        let __formatcl_padding = __formatcl_ruler.justify_padding(
+                            __formatcl_min_columns,
+                            #col_inc,
+                            #min_pad,
+                        );
+                        #overflow_output
+                        #output
+                    }
                 }
                 .to_tokens(tokens)
             }
         }
     }
+    Ok(())
+}
+
+/// Returns the tokens for the index of the pass of the iteration `loops` deep, and the
+/// statements that count the passes if the body uses `~#`. Each depth has its own counter, as
+/// a nested iteration is written out in the body of the enclosing one.
+fn count_passes(
+    directives: &[Directive],
+    loops: usize,
+) -> (
+    proc_macro2::TokenStream,
+    Option<proc_macro2::TokenStream>,
+    Option<proc_macro2::TokenStream>,
+) {
+    let pass = format_ident!("__formatcl_pass_{loops}");
+    let (count, next) = uses_loop_index(directives)
+        .then(|| (quote!(let mut #pass: usize = 0;), quote!(#pass += 1;)))
+        .unzip();
+    (quote!(#pass), count, next)
+}
+
+/// Whether the directives print the index of the pass of an iteration. A `~#` in the body of
+/// a nested iteration prints the index of that one, but is counted here too.
+fn uses_loop_index(directives: &[Directive]) -> bool {
+    any_directive(directives, &|directive| {
+        matches!(directive, Directive::LoopIndex)
+    })
+}
+
+/// Writes the empty part of `~{body~:;empty~}`. As with the choices of `~[`, the arguments it
+/// uses are not consumed.
+fn write_empty(
+    expressions: &Arguments,
+    empty: &Option<Vec<Directive>>,
+    tokens: &mut proc_macro2::TokenStream,
+    writer: &Expr,
+    dyn_args: bool,
+) -> Result<(), String> {
+    if let Some(empty) = empty {
+        write_expressions(
+            &mut expressions.clone(),
+            empty,
+            tokens,
+            writer.clone(),
+            dyn_args,
+        )?;
+    }
+    Ok(())
+}
+
+/// Writes the empty part of `~{body~:;empty~}` when the list in `__formatcl_elements` has
+/// no elements. This comes before `__formatcl_iteration` is shadowed, as the empty part takes
+/// its arguments from the enclosing iteration.
+fn check_empty(
+    expressions: &Arguments,
+    empty: &Option<Vec<Directive>>,
+    writer: &Expr,
+    dyn_args: bool,
+) -> Result<Option<proc_macro2::TokenStream>, String> {
+    if empty.is_none() {
+        return Ok(None);
+    }
+    let mut block = proc_macro2::TokenStream::new();
+    write_empty(expressions, empty, &mut block, writer, dyn_args)?;
+    Ok(Some(quote! {
+        if __formatcl_elements.peek().is_none() {
+            #block
+        }
+    }))
+}
+
+/// Returns the tokens to declare the pass counter for `~n{`, and to stop the loop once it
+/// has made `n` passes.
+fn limit_passes(
+    max_passes: &Option<usize>,
+) -> (
+    Option<proc_macro2::TokenStream>,
+    Option<proc_macro2::TokenStream>,
+) {
+    max_passes
+        .map(|max_passes| {
+            (
+                quote!(let mut __formatcl_passes: usize = 0;),
+                quote! {
+                    if __formatcl_passes == #max_passes {
+                        break;
+                    }
+                    __formatcl_passes += 1;
+                },
+            )
+        })
+        .unzip()
+}
+
+/// Writes the next argument using the given format spec, padding the output to at least
+/// `min_columns`.
+fn write_padded(
+    expressions: &mut Arguments,
+    padding: &Padding,
+    spec: &str,
+    convert: proc_macro2::TokenStream,
+    tokens: &mut proc_macro2::TokenStream,
+    writer: &Expr,
+    dyn_args: bool,
+) {
+    // With `dyn_args`, every argument is formatted by the same code for `&dyn Display` or
+    // `&dyn Debug`, rather than by code generated for its type.
+    let erase = dyn_args.then(|| {
+        let format_trait = if is_debug(spec) {
+            quote!(Debug)
+        } else {
+            quote!(Display)
+        };
+        quote! {
+            let __formatcl_arg: &dyn ::core::fmt::#format_trait = &__formatcl_arg;
+        }
+    });
+
+    if *padding == Padding::default() {
+        let expression = expressions
+            .next()
+            .expect("argument count checked when parsing");
+        let check = check_arg(expression, spec);
+        quote! {
+            {
+                let __formatcl_arg = &#expression;
+                #convert
+                #check
+                #erase
+                let r = write!(#writer, #spec, __formatcl_arg);
+                if r.is_err() {
+                    break '__format_cl__loop r;
+                }
+            }
+        }
+        .to_tokens(tokens);
+        return;
+    }
+
+    let min_columns = num_value(&padding.min_columns, expressions);
+    let col_inc = num_value(&padding.col_inc, expressions);
+    let min_pad = num_value(&padding.min_pad, expressions);
+    let pad_char = char_value(&padding.pad_char, expressions);
+    let max_columns = padding
+        .max_columns
+        .as_ref()
+        .map(|max_columns| num_value(max_columns, expressions));
+    let expression = expressions
+        .next()
+        .expect("argument count checked when parsing");
+    let check = check_arg(expression, spec);
+    let fill = fill(writer, quote!(__formatcl_padding));
+
+    // Output wider than the maximum is cut, leaving a column for the pad character to mark
+    // it, and measured again.
+    let (truncate, write) = match max_columns {
+        Some(max_columns) => (
+            quote! {
+                let __formatcl_max_columns: usize = #max_columns;
+                let __formatcl_cut = __formatcl_ruler.truncation(__formatcl_max_columns);
+                if let ::core::option::Option::Some(__formatcl_cut) = __formatcl_cut {
+                    __formatcl_ruler = ::clformat::Ruler::default();
+                    let _ = ::core::fmt::Write::write_fmt(
+                        &mut ::clformat::Truncate::new(&mut __formatcl_ruler, __formatcl_cut),
+                        format_args!(#spec, __formatcl_arg),
+                    );
+                    if __formatcl_max_columns > 0 {
+                        let _ = __formatcl_ruler.write_char(__formatcl_pad_char);
+                    }
+                }
+            },
+            quote! {
+                let r = match __formatcl_cut {
+                    ::core::option::Option::Some(__formatcl_cut) => {
+                        let r = write!(
+                            ::clformat::Truncate::new(&mut #writer, __formatcl_cut),
+                            #spec,
+                            __formatcl_arg
+                        );
+                        if r.is_ok() && __formatcl_max_columns > 0 {
+                            #writer.write_char(__formatcl_pad_char)
+                        } else {
+                            r
+                        }
+                    }
+                    ::core::option::Option::None => write!(#writer, #spec, __formatcl_arg),
+                };
+            },
+        ),
+        None => (
+            quote!(),
+            quote!(let r = write!(#writer, #spec, __formatcl_arg);),
+        ),
+    };
+    let (left_fill, right_fill) = if padding.pad_left {
+        (Some(fill), None)
+    } else {
+        (None, Some(fill))
+    };
+
+    quote! {
+        {
+            let __formatcl_arg = &#expression;
+            #convert
+            #check
+            #erase
+            let __formatcl_pad_char: char = #pad_char;
+            let mut __formatcl_ruler = ::clformat::Ruler::default();
+            let _ = ::core::fmt::Write::write_fmt(
+                &mut __formatcl_ruler,
+                format_args!(#spec, __formatcl_arg),
+            );
+            #truncate
+            let __formatcl_padding = __formatcl_ruler.padding(#min_columns, #col_inc, #min_pad);
+
+            #left_fill
+            #write
+            if r.is_err() {
+                break '__format_cl__loop r;
+            }
+            #right_fill
+        }
+    }
+    .to_tokens(tokens)
+}
+
+/// Borrows a list given to `~{` when it is a variable or a field, so that the caller keeps it.
+/// Any other argument, such as `&list` or `list.iter()`, is already an iterable value.
+fn borrow_list(expression: &Expr) -> proc_macro2::TokenStream {
+    match expression {
+        _ if is_bound_value(expression) => quote_spanned! {expression.span()=>
+            ::clformat::__private::list_arg(#expression)
+        },
+        Expr::Path(_) | Expr::Field(_) | Expr::Paren(_) => quote_spanned! {expression.span()=>
+            {
+                #[allow(unused_imports)]
+                use ::clformat::__private::{BorrowedListKind as _, ClonedListKind as _};
+                (&#expression).clformat_list()
+            }
+        },
+        _ => quote_spanned! {expression.span()=>
+            ::clformat::__private::list_arg(#expression)
+        },
+    }
 }
 
-#[derive(Clone)]
-struct IndexedExpression<'a> {
-    count: usize,
-    expr: &'a Expr,
+/// Checks the argument implements the trait `spec` formats it with, spanned to the argument so
+/// that the error points at it and names the directive.
+fn check_arg(expression: &Expr, spec: &str) -> proc_macro2::TokenStream {
+    let check = if is_debug(spec) {
+        quote!(debug_arg)
+    } else {
+        quote!(display_arg)
+    };
+    // `__formatcl_arg` is resolved where the macro is called, which differs from where the
+    // argument was written when it is passed through a `macro_rules!` macro.
+    let span = expression
+        .span()
+        .resolved_at(proc_macro2::Span::call_site());
+    quote_spanned!(span=> ::clformat::__private::#check(&__formatcl_arg);)
 }
 
-impl<'a> Iterator for IndexedExpression<'a> {
-    type Item = &'a Expr;
+/// Whether `spec` formats with `Debug` rather than `Display`.
+fn is_debug(spec: &str) -> bool {
+    spec.ends_with("?}")
+}
 
-    fn next(&mut self) -> Option<Self::Item> {
-        self.count += 1;
-        Some(self.expr)
+/// The line ending written by `~%`.
+fn newline(crlf: bool) -> &'static str {
+    if crlf {
+        "\r\n"
+    } else {
+        "\n"
+    }
+}
+
+/// Writes `count` copies of the alignment pad character.
+fn fill(writer: &Expr, count: proc_macro2::TokenStream) -> proc_macro2::TokenStream {
+    quote! {
+        for _ in 0..#count {
+            let r = write!(#writer, "{}", __formatcl_pad_char);
+            if r.is_err() {
+                break '__format_cl__loop r;
+            }
+        }
+    }
+}
+
+/// Returns the tokens for a numeric parameter, taking the next argument if the value
+/// is to be read from the arguments.
+fn num_value(value: &Value<usize>, expressions: &mut Arguments) -> proc_macro2::TokenStream {
+    match value {
+        Value::Literal(value) => quote!(#value),
+        Value::FromArg => {
+            let expression = expressions
+                .next()
+                .expect("argument count checked when parsing");
+            quote_spanned! {expression.span()=>
+                ::clformat::__private::ParamArg::param(&(#expression))
+            }
+        }
+        Value::ArgCount => expressions.remaining(),
+    }
+}
+
+/// Returns the tokens for the comparison that `~^` makes with its parameters, if it has any.
+fn break_condition(
+    params: &[Value<usize>],
+    expressions: &mut Arguments,
+) -> Option<proc_macro2::TokenStream> {
+    let values = params
+        .iter()
+        .map(|param| num_value(param, expressions))
+        .collect::<Vec<_>>();
+    match values.as_slice() {
+        [] => None,
+        [n] => Some(quote!(#n == 0)),
+        [n, m] => Some(quote!(#n == #m)),
+        // `m` is bound so that an argument is only used once.
+        [n, m, p] => Some(quote! {{
+            let (__formatcl_n, __formatcl_m, __formatcl_p) = (#n, #m, #p);
+            __formatcl_n <= __formatcl_m && __formatcl_m <= __formatcl_p
+        }}),
+        _ => unreachable!("the parser rejects more than three parameters"),
+    }
+}
+
+/// Returns the tokens for a character parameter, taking the next argument if the value
+/// is to be read from the arguments.
+fn char_value(value: &Value<char>, expressions: &mut Arguments) -> proc_macro2::TokenStream {
+    match value {
+        Value::Literal(value) => quote!(#value),
+        Value::FromArg => {
+            let expression = expressions
+                .next()
+                .expect("argument count checked when parsing");
+            quote!(#expression)
+        }
+        Value::ArgCount => unreachable!("the parser rejects `#` for character parameters"),
     }
 }
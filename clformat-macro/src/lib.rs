@@ -2,10 +2,11 @@ use proc_macro::TokenStream;
 use quote::quote;
 use syn::parse_macro_input;
 
+mod arguments;
+mod compile;
 mod format_input;
-mod parse;
-mod parse_error;
 
+use compile::CompiledFormat;
 use format_input::FormatInput;
 
 #[proc_macro]
@@ -14,3 +15,43 @@ pub fn clformat(item: TokenStream) -> TokenStream {
 
     quote!({ #ast }).into()
 }
+
+/// Like `clformat!`, but evaluates to a `Result` containing the writer's error rather than
+/// panicking if a write fails.
+#[proc_macro]
+pub fn try_clformat(item: TokenStream) -> TokenStream {
+    let ast: FormatInput = parse_macro_input!(item);
+    let ast = ast.fallible();
+
+    quote!({ #ast }).into()
+}
+
+/// Like `clformat!`, but writes a newline after the output, as `writeln!` does.
+#[proc_macro]
+pub fn clformat_writeln(item: TokenStream) -> TokenStream {
+    let ast: FormatInput = parse_macro_input!(item);
+    let ast = ast.with_newline();
+
+    quote!({ #ast }).into()
+}
+
+/// Like `clformat!` without a destination, evaluating to a value that implements `Display`.
+/// The directives are written to the formatter each time the value is formatted, so nothing
+/// is allocated.
+#[proc_macro]
+pub fn clformat_args(item: TokenStream) -> TokenStream {
+    let ast = parse_macro_input!(item with FormatInput::parse_display);
+
+    quote!({ #ast }).into()
+}
+
+/// Defines a macro for a format string that is used in several places, so that it is written
+/// and checked once. `clformat_compile!(entry, "~A: ~D")` defines `entry!`, which takes a
+/// destination and arguments as `clformat!` does, so `entry!(nil, "toes", 10)` evaluates to
+/// `"toes: 10"`.
+#[proc_macro]
+pub fn clformat_compile(item: TokenStream) -> TokenStream {
+    let ast: CompiledFormat = parse_macro_input!(item);
+
+    quote!(#ast).into()
+}
@@ -0,0 +1,7 @@
+//! Parses Common Lisp format strings into directives.
+//! Shared by the `clformat!` macro and the runtime formatter in `clformat`.
+mod parse;
+mod parse_error;
+
+pub use parse::{parse_format_string, Alignment, Directive, Navigation, Overflow, Padding, Value};
+pub use parse_error::ParseError;
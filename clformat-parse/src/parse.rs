@@ -0,0 +1,2152 @@
+#![allow(warnings)]
+use std::{fmt::Write, io::Write as _, iter::Peekable, ops::Deref};
+
+use crate::parse_error::{FormatError, ParseError};
+use nom::{
+    branch::alt,
+    bytes::complete::{tag, tag_no_case, take_till, take_till1, take_while},
+    character::complete::{anychar, digit1, satisfy},
+    combinator::{cut, eof, map, map_res, not, opt},
+    error::FromExternalError,
+    multi::{many0, many1, many_till, separated_list0},
+    sequence::{delimited, preceded, terminated, tuple},
+    IResult,
+};
+
+/// A prefix parameter that may only be known once the arguments are available.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Value<T> {
+    /// The value was given in the format string.
+    Literal(T),
+    /// `V` - the value is taken from the next argument.
+    FromArg,
+    /// `#` - the number of arguments remaining.
+    ArgCount,
+}
+
+/// The parameters controlling how `~A` and `~S` pad their output.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Padding {
+    pub min_columns: Value<usize>,
+    pub col_inc: Value<usize>,
+    pub min_pad: Value<usize>,
+    pub pad_char: Value<char>,
+    /// An extension to Lisp: the fifth parameter cuts the output to this many columns,
+    /// ending it with the pad character when it doesn't fit, so `~,,,'…,8A` prints
+    /// `Frobozz Magic` as `Frobozz…`.
+    pub max_columns: Option<Value<usize>>,
+    /// `@` - pad on the left rather than the right.
+    pub pad_left: bool,
+}
+
+impl Default for Padding {
+    fn default() -> Self {
+        Self {
+            min_columns: Value::Literal(0),
+            col_inc: Value::Literal(1),
+            min_pad: Value::Literal(0),
+            pad_char: Value::Literal(' '),
+            max_columns: None,
+            pad_left: false,
+        }
+    }
+}
+
+impl Padding {
+    /// `mincol,colinc,minpad,padchar,maxcol`
+    fn new(params: &Params, modifiers: &Modifiers) -> Result<Self, String> {
+        Ok(Self {
+            min_columns: params.get_num_value(0, 0)?,
+            col_inc: params.get_num_value(1, 1)?,
+            min_pad: params.get_num_value(2, 0)?,
+            pad_char: params.get_char_value(3, ' ')?,
+            max_columns: params.get_optional_num_value(4)?,
+            pad_left: modifiers.at,
+        })
+    }
+}
+
+/// Which way `~*` moves among the arguments.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Navigation {
+    /// `~n*` - skip the next `n` arguments.
+    Forward,
+    /// `~n:*` - back up so the previous `n` arguments are used again.
+    Backward,
+    /// `~n@*` - jump to the argument at index `n`.
+    Absolute,
+}
+
+/// Where `~<` inserts padding other than between the segments.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Alignment {
+    /// `@` - padding after the last segment.
+    Left,
+    /// `:` - padding before the first segment.
+    Right,
+    /// `:@` - padding before the first and after the last segment.
+    Centre,
+    /// Padding only between the segments. A single segment is padded after, as with `Left`.
+    Justify,
+}
+
+impl Alignment {
+    /// Whether padding is inserted before the first segment.
+    pub fn pads_before(&self) -> bool {
+        matches!(self, Self::Right | Self::Centre)
+    }
+
+    /// Whether padding is inserted after the last segment.
+    pub fn pads_after(&self, segments: usize) -> bool {
+        match self {
+            Self::Left | Self::Centre => true,
+            Self::Right => false,
+            Self::Justify => segments <= 1,
+        }
+    }
+}
+
+impl From<Modifiers> for Alignment {
+    fn from(value: Modifiers) -> Self {
+        match (value.colon, value.at) {
+            (true, true) => Self::Centre,
+            (true, false) => Self::Right,
+            (false, true) => Self::Left,
+            (false, false) => Self::Justify,
+        }
+    }
+}
+
+/// The first clause of `~<` when it is terminated by `~spare,width:;`. It is written before
+/// the justified text if the text would run past the end of the line.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Overflow {
+    /// The number of columns that must be left spare on the line.
+    pub spare: usize,
+    pub line_width: usize,
+    pub directives: Vec<Directive>,
+}
+
+/// The Directives that are supported.
+/// Attempts to conform to the [Hyperspec].
+///
+/// [Hyperspec]: https://www.lispworks.com/documentation/HyperSpec/Body/22_c.htm
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Directive {
+    Align {
+        min_columns: Value<usize>,
+        col_inc: usize,
+        min_pad: usize,
+        pad_char: Value<char>,
+        direction: Alignment,
+        /// The sections separated by `~;`.
+        segments: Vec<Vec<Directive>>,
+        overflow: Option<Overflow>,
+    },
+    /// `~*`, `~:*` and `~@*` - move among the arguments without printing them. `count` is
+    /// the number of arguments to move by, or the index to jump to.
+    ArgNav {
+        count: usize,
+        direction: Navigation,
+    },
+    /// `~^` - stop the iteration if there are no arguments left. `~:^` stops the enclosing
+    /// iteration if it has no elements left.
+    ///
+    /// With parameters, `~n^` stops if `n` is zero, `~n,m^` if `n == m` and `~n,m,p^` if
+    /// `n <= m <= p`.
+    Break {
+        outer: bool,
+        params: Vec<Value<usize>>,
+    },
+    /// ``~`name`A`` - the directive uses the variable `name` in scope rather than the
+    /// next argument.
+    Captured {
+        name: String,
+        directive: Box<Directive>,
+    },
+    Conditional {
+        boolean: bool,
+        consumes: bool,
+        /// `~n[`, `~V[` or `~#[` - the choice is selected by a parameter rather than the
+        /// next argument.
+        selector: Option<Value<usize>>,
+        default: Option<Vec<Directive>>,
+        choices: Vec<Vec<Directive>>,
+    },
+    Decimal {
+        min_columns: Value<usize>,
+        pad_char: Value<char>,
+        /// Taken from the locale when omitted, which defaults to `,`.
+        comma_char: Option<Value<char>>,
+        /// Taken from the locale when omitted, which defaults to 3.
+        comma_interval: Option<Value<usize>>,
+        /// An extension to Lisp: the interval between commas after the first group, such as
+        /// 2 for `~,,,3,2:D` to print `12,34,567`.
+        secondary_interval: Option<Value<usize>>,
+        print_commas: bool,
+        print_sign: bool,
+        /// `~X`, `~O` and `~B` print in base 16, 8 and 2.
+        radix: u32,
+        /// An extension to Lisp: `~@X`, `~@O` and `~@B` print a Rust style `0x`, `0o` or `0b`
+        /// prefix rather than the sign of positive numbers.
+        prefix: bool,
+        /// An extension to Lisp: `~x` prints digits above 9 in lowercase, where Lisp ignores the
+        /// case of the directive.
+        lowercase: bool,
+        /// An extension to Lisp: a sixth parameter of 1 pads between the sign and the digits,
+        /// so `~6,,,,,1D` prints `-42` as `-   42`, where `~6D` pads before the sign.
+        pad_after_sign: bool,
+    },
+    Float {
+        width: Value<usize>,
+        /// Without a number of decimal places, as many digits are printed as are needed.
+        num_decimal_places: Option<Value<usize>>,
+        pad_char: char,
+        /// `@` - print the sign of positive numbers, and of negative zero.
+        print_sign: bool,
+        /// An extension to Lisp: `:` - print `.5` rather than `0.5`.
+        drop_leading_zero: bool,
+        /// An extension to Lisp: a seventh parameter of 1 rounds halves of the last decimal
+        /// place away from zero, as written in decimal, so `~,2,,,,,1F` prints `2.675` as
+        /// `2.68` where `~,2F` prints `2.67`, the nearest to the binary value.
+        round_half_up: bool,
+        /// An extension to Lisp: `#` - pad after the number rather than before it, so that it
+        /// is left justified in the field.
+        left_justify: bool,
+    },
+    Iteration {
+        /// `:` - each element is a sublist of arguments for the directives.
+        sublists: bool,
+        /// `@` - iterate over the remaining arguments rather than taking a list argument.
+        remaining: bool,
+        /// `~n{` - stop after `n` passes.
+        max_passes: Option<usize>,
+        /// Empty for `~{~}`, in which case the directives are taken from an argument
+        /// before the list.
+        directives: Vec<Directive>,
+        /// An extension to Lisp: `~{body~:;empty~}` writes `empty` rather than the body when
+        /// there are no elements. As with the choices of `~[`, the arguments it uses are not
+        /// consumed.
+        empty: Option<Vec<Directive>>,
+    },
+    Literal(String),
+    /// An extension to Lisp: `~#` inside `~{` prints the index of the pass, counting from
+    /// zero.
+    LoopIndex,
+    /// `~n%` - write `count` newlines.
+    Newline {
+        count: Value<usize>,
+        /// An extension to Lisp: `~:%` writes `\r\n` line endings, as protocols such as HTTP
+        /// require.
+        crlf: bool,
+    },
+    TildeA {
+        padding: Padding,
+        /// `:` - print `None` as `()`.
+        print_nil: bool,
+        /// An extension to Lisp: the sixth parameter of `~:A` joins the elements of a list
+        /// with this character and a space, so `~,,,,,',:A` prints `1, 2, 3` rather than
+        /// `(1 2 3)`.
+        separator: Option<char>,
+        /// An extension to Lisp: `#` - print with the alternate form of `Display`, as `{:#}`
+        /// does. It is written after the other modifiers, right before the `A`.
+        alternate: bool,
+    },
+    TildeS {
+        padding: Padding,
+        /// An extension to Lisp: `#` - pretty print with `{:#?}`.
+        alternate: bool,
+    },
+    /// `~W` - prints with `Debug`.
+    Write {
+        /// `:` - pretty print with `{:#?}`.
+        pretty: bool,
+    },
+}
+
+impl Directive {
+    fn new_conditional<'a>(
+        input: &'a str,
+        boolean: bool,
+        consumes: bool,
+        selector: Option<Value<usize>>,
+        choices: Vec<Vec<Directive>>,
+        default: Option<Vec<Directive>>,
+    ) -> Result<Self, nom::Err<FormatError<&'a str>>> {
+        if selector.is_some() && (boolean || consumes) {
+            return Err(nom::Err::Failure(FormatError::from_external_error(
+                input,
+                nom::error::ErrorKind::Tag,
+                "`~:[` and `~@[` do not take a parameter",
+            )));
+        }
+
+        if boolean && (choices.len() != 2 || default.is_some()) {
+            return Err(nom::Err::Error(FormatError::from_external_error(
+                input,
+                nom::error::ErrorKind::Tag,
+                "boolean conditional must specify exactly two sections",
+            )));
+        }
+
+        if let (Some(Value::Literal(index)), None) = (selector, &default) {
+            if index >= choices.len() {
+                return Err(nom::Err::Failure(FormatError::from_external_error(
+                    input,
+                    nom::error::ErrorKind::Tag,
+                    format!(
+                        "`~{index}[` selects choice {index}, but there are only {} choices",
+                        choices.len()
+                    ),
+                )));
+            }
+        }
+
+        if consumes && (choices.len() != 1 || default.is_some()) {
+            return Err(nom::Err::Error(FormatError::from_external_error(
+                input,
+                nom::error::ErrorKind::Tag,
+                "consume conditional must specify exactly one section",
+            )));
+        }
+
+        Ok(Self::Conditional {
+            boolean,
+            consumes,
+            selector,
+            choices,
+            default,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum State {
+    Normal,
+    Loop,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self::Normal
+    }
+}
+
+/// Parses the format string, returning where and what the problem is if it is invalid.
+pub fn parse_format_string(format_string: &str) -> Result<Vec<Directive>, ParseError> {
+    parse_string(format_string)
+        .map_err(|err| {
+            let err = match err {
+                nom::Err::Incomplete(_) => unreachable!(),
+                nom::Err::Error(err) => err,
+                nom::Err::Failure(err) => err,
+            };
+            ParseError {
+                message: err.to_string(),
+                position: err.error_pos(format_string),
+            }
+        })
+        .map(|(_, result)| merge_literals(result))
+}
+
+/// Joins adjacent literals, such as the text either side of `~~`, so the macro writes them
+/// with a single call. Blocks are merged within each of their sections.
+fn merge_literals(directives: Vec<Directive>) -> Vec<Directive> {
+    let mut merged: Vec<Directive> = Vec::with_capacity(directives.len());
+    for directive in directives {
+        let directive = match directive {
+            Directive::Align {
+                min_columns,
+                col_inc,
+                min_pad,
+                pad_char,
+                direction,
+                segments,
+                overflow,
+            } => Directive::Align {
+                min_columns,
+                col_inc,
+                min_pad,
+                pad_char,
+                direction,
+                segments: segments.into_iter().map(merge_literals).collect(),
+                overflow: overflow.map(|overflow| Overflow {
+                    directives: merge_literals(overflow.directives),
+                    ..overflow
+                }),
+            },
+            Directive::Conditional {
+                boolean,
+                consumes,
+                selector,
+                default,
+                choices,
+            } => Directive::Conditional {
+                boolean,
+                consumes,
+                selector,
+                default: default.map(merge_literals),
+                choices: choices.into_iter().map(merge_literals).collect(),
+            },
+            Directive::Iteration {
+                sublists,
+                remaining,
+                max_passes,
+                directives,
+                empty,
+            } => Directive::Iteration {
+                sublists,
+                remaining,
+                max_passes,
+                directives: merge_literals(directives),
+                empty: empty.map(merge_literals),
+            },
+            Directive::Literal(text) => match merged.last_mut() {
+                Some(Directive::Literal(previous)) => {
+                    previous.push_str(&text);
+                    continue;
+                }
+                _ => Directive::Literal(text),
+            },
+            directive => directive,
+        };
+        merged.push(directive);
+    }
+    merged
+}
+
+type FormatResult<'a, T> = IResult<&'a str, T, FormatError<&'a str>>;
+
+/// http://www.lispworks.com/documentation/lw50/CLHS/Body/22_c.htm
+fn parse_string(input: &str) -> FormatResult<Vec<Directive>> {
+    map(
+        many_till(cut(segment(State::Normal)), eof),
+        |(directives, _)| {
+            // Ignore the eof parser result.
+            directives
+        },
+    )(input)
+}
+
+fn segment(state: State) -> impl Fn(&str) -> FormatResult<Directive> {
+    move |input| {
+        alt((
+            literal,
+            alignment,
+            iteration,
+            conditional,
+            directive(state),
+            loop_index(state),
+        ))(input)
+    }
+}
+
+/// `~#` is only the loop index when the `#` can't be a parameter, so `~#^` and `~#[` still
+/// take the number of arguments remaining, while `~#: ` and `~#)` print the index.
+fn loop_index(state: State) -> impl Fn(&str) -> FormatResult<Directive> {
+    move |input| {
+        let (rest, _) = terminated(
+            tag("~#"),
+            not(satisfy(|c| c.is_alphanumeric() || ",@'".contains(c))),
+        )(input)?;
+        if state != State::Loop {
+            return Err(nom::Err::Error(FormatError::from_external_error(
+                input,
+                nom::error::ErrorKind::Tag,
+                "directive `#` not inside loop",
+            )));
+        }
+        Ok((rest, Directive::LoopIndex))
+    }
+}
+
+fn literal(input: &str) -> FormatResult<Directive> {
+    map(take_till1(|c| c == '~'), |s: &str| {
+        Directive::Literal(s.to_string())
+    })(input)
+}
+
+fn params_to_align(
+    params: Params,
+    modifiers: Modifiers,
+    segments: Vec<Vec<Directive>>,
+    overflow: Option<Overflow>,
+) -> Result<Directive, String> {
+    let min_columns = params.get_num_value(0, 0)?;
+    let col_inc = params.get_num(1, 0)? as usize;
+    let min_pad = params.get_num(2, 0)? as usize;
+    let pad_char = params.get_char_value(3, ' ')?;
+
+    Ok((Directive::Align {
+        min_columns,
+        col_inc,
+        min_pad,
+        pad_char,
+        direction: modifiers.into(),
+        segments,
+        overflow,
+    }))
+}
+
+/// Conditional is a series of directive separated by `~:` and
+/// enclosed by `~[..~]`.
+fn conditional(input: &str) -> FormatResult<Directive> {
+    let start = input;
+    let (input, _) = tag("~")(input)?;
+    let (input, params) = params(input)?;
+    let (input, modifiers) = modifiers(input)?;
+    let (mut input, _) = tag("[")(input)?;
+
+    let mut choices = Vec::new();
+    let mut current = Vec::new();
+    let mut default = None;
+    let boolean = modifiers.colon;
+    let consumes = modifiers.at;
+    let selector = params.get_optional_num_value(0).map_err(|err| {
+        nom::Err::Failure(FormatError::from_external_error(
+            start,
+            nom::error::ErrorKind::Tag,
+            err,
+        ))
+    })?;
+
+    loop {
+        if input.starts_with("~]") {
+            // An empty choice after `~;` is still a choice, as in `~:[yes~;~]`.
+            if default.is_none() && (!current.is_empty() || !choices.is_empty()) {
+                choices.push(current);
+            }
+
+            return Ok((
+                &input[2..],
+                Directive::new_conditional(start, boolean, consumes, selector, choices, default)?,
+            ));
+        } else if input.is_empty() {
+            return Err(unterminated(start, "~[", "~]"));
+        } else if input.starts_with("~;") {
+            if default.is_some() {
+                return Err(nom::Err::Error(FormatError::from_external_error(
+                    input,
+                    nom::error::ErrorKind::Tag,
+                    "only the last conditional can be default",
+                )));
+            }
+
+            // We are at the start of a new choice
+            choices.push(std::mem::take(&mut current));
+            input = &input[2..];
+        } else if input.starts_with("~:;") {
+            // The default case.
+            choices.push(std::mem::take(&mut current));
+            default = Some(vec![]);
+            input = &input[3..];
+        } else {
+            let (new_input, directive) = segment(State::Loop)(input)?;
+            input = new_input;
+
+            match &mut default {
+                Some(default) => default.push(directive),
+                None => current.push(directive),
+            }
+        }
+    }
+}
+
+/// Alignment is a series of segments separated by `~;` and enclosed by `~<..~>`.
+/// There can optionally be params and modifiers to determine how to align
+/// the enclosed segments.
+fn alignment(input: &str) -> FormatResult<Directive> {
+    let start = input;
+    let (input, _) = tag("~")(input)?;
+    let (input, params) = params(input)?;
+    let (input, modifiers) = modifiers(input)?;
+    let (mut input, _) = tag("<")(input)?;
+
+    let mut segments = Vec::new();
+    let mut current = Vec::new();
+    let mut overflow = None;
+
+    loop {
+        if input.is_empty() {
+            return Err(unterminated(start, "~<", "~>"));
+        } else if input.starts_with("~>") {
+            segments.push(current);
+            let directive =
+                params_to_align(params, modifiers, segments, overflow).map_err(|err| {
+                    nom::Err::Error(FormatError::from_external_error(
+                        start,
+                        nom::error::ErrorKind::Tag,
+                        err,
+                    ))
+                })?;
+            return Ok((&input[2..], directive));
+        } else if input.starts_with("~;") {
+            segments.push(std::mem::take(&mut current));
+            input = &input[2..];
+        } else if let Ok((new_input, separator)) = overflow_separator(input) {
+            if !segments.is_empty() || overflow.is_some() {
+                return Err(nom::Err::Failure(FormatError::from_external_error(
+                    input,
+                    nom::error::ErrorKind::Tag,
+                    "only the first segment can be terminated by `~:;`",
+                )));
+            }
+
+            let (spare, line_width) = separator.map_err(|err| {
+                nom::Err::Failure(FormatError::from_external_error(
+                    input,
+                    nom::error::ErrorKind::Tag,
+                    err,
+                ))
+            })?;
+            overflow = Some(Overflow {
+                spare,
+                line_width,
+                directives: std::mem::take(&mut current),
+            });
+            input = new_input;
+        } else {
+            let (new_input, directive) = segment(State::Loop)(input)?;
+            input = new_input;
+            current.push(directive);
+        }
+    }
+}
+
+/// Parses the `~spare,width:;` that ends the overflow clause of `~<`, returning the spare
+/// columns and the line width. Lisp defaults the line width to 72.
+fn overflow_separator(input: &str) -> FormatResult<Result<(usize, usize), String>> {
+    map(delimited(tag("~"), params, tag(":;")), |params: Params| {
+        Ok((
+            params.get_num(0, 0)? as usize,
+            params.get_num(1, 72)? as usize,
+        ))
+    })(input)
+}
+
+/// Iteration as a series of directives enclosed by `~{..~}`.
+fn iteration(input: &str) -> FormatResult<Directive> {
+    let start = input;
+    let (input, _) = tag("~")(input)?;
+    let (input, params) = params(input)?;
+    let (input, modifiers) = modifiers(input)?;
+    let (mut input, _) = tag("{")(input)?;
+    let max_passes = params.get_optional_num(0).map_err(|err| {
+        nom::Err::Failure(FormatError::from_external_error(
+            start,
+            nom::error::ErrorKind::Tag,
+            err,
+        ))
+    })?;
+    let mut result = Vec::new();
+    // The body, once `~:;` has been reached and `result` holds the empty part.
+    let mut body = None;
+    let iteration = |directives: Vec<Directive>, empty: Option<Vec<Directive>>| {
+        let failure = |message| {
+            nom::Err::Failure(FormatError::from_external_error(
+                start,
+                nom::error::ErrorKind::Tag,
+                message,
+            ))
+        };
+        // An empty body takes the body from the arguments, which we only do for plain lists.
+        if directives.is_empty() && (modifiers.colon || modifiers.at) {
+            return Err(failure("`~:{~}` and `~@{~}` must have a body"));
+        }
+        if directives.is_empty() && empty.is_some() {
+            return Err(failure("`~{` must have a body before `~:;`"));
+        }
+
+        Ok(Directive::Iteration {
+            sublists: modifiers.colon,
+            remaining: modifiers.at,
+            max_passes,
+            directives,
+            empty,
+        })
+    };
+
+    loop {
+        if input.starts_with("~}") {
+            let iteration = match body {
+                Some(body) => iteration(body, Some(result))?,
+                None => iteration(result, None)?,
+            };
+            return Ok((&input[2..], iteration));
+        } else if input.starts_with("~:;") {
+            if body.is_some() {
+                return Err(nom::Err::Failure(FormatError::from_external_error(
+                    input,
+                    nom::error::ErrorKind::Tag,
+                    "`~{` can only have one `~:;`",
+                )));
+            }
+            body = Some(std::mem::take(&mut result));
+            input = &input[3..];
+        } else if input.is_empty() {
+            return Err(unterminated(start, "~{", "~}"));
+        } else {
+            let (new_input, directive) = segment(State::Loop)(input)?;
+            input = new_input;
+            result.push(directive);
+        }
+    }
+}
+
+/// The error for a block directive starting at `input` that reaches the end of the format
+/// string without its closing directive.
+fn unterminated<'a>(input: &'a str, open: &str, close: &str) -> nom::Err<FormatError<&'a str>> {
+    nom::Err::Failure(FormatError::from_external_error(
+        input,
+        nom::error::ErrorKind::Eof,
+        format!("unterminated `{open}` directive, expected a closing `{close}`"),
+    ))
+}
+
+/// Parse the directive - a supported character preceeded by a `~` and optionally
+/// a captured variable.
+fn directive(state: State) -> impl Fn(&str) -> FormatResult<Directive> {
+    move |input| {
+        map_res(
+            preceded(tag("~"), tuple((opt(captured), directive_char(state)))),
+            |(captured, directive)| match captured {
+                Some(name) => captured_directive(name, directive),
+                None => Ok(directive),
+            },
+        )(input)
+        // Errors in the parameters or the directive character point at the `~`.
+        .map_err(|err| err.map(|err| err.at(input)))
+    }
+}
+
+/// Parses the name of a captured variable enclosed in backticks, e.g. `` `name` `` or
+/// `` `point.x` ``.
+fn captured(input: &str) -> FormatResult<&str> {
+    preceded(
+        tag("`"),
+        cut(map_res(
+            terminated(take_till(|c| c == '`'), tag("`")),
+            |name: &str| {
+                let valid = !name.starts_with(|c: char| c.is_ascii_digit())
+                    && name.split('.').all(|part| {
+                        !part.is_empty() && part.chars().all(|c| c.is_alphanumeric() || c == '_')
+                    });
+                if valid {
+                    Ok(name)
+                } else {
+                    Err(format!("invalid captured variable `{name}`"))
+                }
+            },
+        )),
+    )(input)
+}
+
+/// Wraps a directive that prints a single argument so that it uses the captured variable.
+fn captured_directive(name: &str, directive: Directive) -> Result<Directive, String> {
+    let from_arg = match &directive {
+        Directive::TildeA { padding, .. } | Directive::TildeS { padding, .. } => {
+            padding.min_columns == Value::FromArg
+                || padding.col_inc == Value::FromArg
+                || padding.min_pad == Value::FromArg
+                || padding.pad_char == Value::FromArg
+                || padding.max_columns == Some(Value::FromArg)
+        }
+        Directive::Decimal {
+            min_columns,
+            pad_char,
+            comma_char,
+            comma_interval,
+            secondary_interval,
+            ..
+        } => {
+            *min_columns == Value::FromArg
+                || *pad_char == Value::FromArg
+                || *comma_char == Some(Value::FromArg)
+                || *comma_interval == Some(Value::FromArg)
+                || *secondary_interval == Some(Value::FromArg)
+        }
+        Directive::Float {
+            width,
+            num_decimal_places,
+            ..
+        } => *width == Value::FromArg || *num_decimal_places == Some(Value::FromArg),
+        Directive::Write { .. } => false,
+        _ => {
+            return Err(format!(
+                "directive cannot use the captured variable `{name}`"
+            ))
+        }
+    };
+
+    if from_arg {
+        return Err("`V` parameter cannot be used with a captured variable".to_string());
+    }
+
+    Ok(Directive::Captured {
+        name: name.to_string(),
+        directive: Box::new(directive),
+    })
+}
+
+/// Parse the directive character and its parameters and modifiers.
+fn directive_char(state: State) -> impl Fn(&str) -> FormatResult<Directive> {
+    move |input| {
+        map_res(
+            tuple((params, modifiers, opt(tag("#")), anychar)),
+            |(params, modifiers, alternate, directive)| match directive.to_ascii_uppercase() {
+                _ if alternate.is_some() && !takes_hash(directive) => {
+                    Err("only `~A`, `~S` and `~F` take the `#` modifier".to_string())
+                }
+                'A' => {
+                    let separator = params.get_optional_char(5)?;
+                    if separator.is_some() && !modifiers.colon {
+                        return Err("only `~:A` takes a separator".to_string());
+                    }
+                    Ok(Directive::TildeA {
+                        padding: Padding::new(&params, &modifiers)?,
+                        print_nil: modifiers.colon,
+                        separator,
+                        alternate: alternate.is_some(),
+                    })
+                }
+                'S' => Ok(Directive::TildeS {
+                    padding: Padding::new(&params, &modifiers)?,
+                    alternate: alternate.is_some(),
+                }),
+                'D' | 'X' | 'O' | 'B' => {
+                    let radix = match directive.to_ascii_uppercase() {
+                        'X' => 16,
+                        'O' => 8,
+                        'B' => 2,
+                        _ => 10,
+                    };
+                    let min_columns = params.get_num_value(0, 0)?;
+                    let pad_char = params.get_char_value(1, ' ')?;
+                    let comma_char = params.get_optional_char_value(2)?;
+                    let comma_interval = params.get_optional_num_value(3)?;
+                    let secondary_interval = params.get_optional_num_value(4)?;
+                    let pad_after_sign = match params.get_num(5, 0)? {
+                        0 => false,
+                        1 => true,
+                        _ => return Err("the sixth parameter of `~D` must be 0 or 1".to_string()),
+                    };
+                    if secondary_interval == Some(Value::Literal(0)) {
+                        return Err(
+                            "the interval between commas after the first group cannot be 0"
+                                .to_string(),
+                        );
+                    }
+
+                    Ok(Directive::Decimal {
+                        min_columns,
+                        pad_char,
+                        comma_char,
+                        comma_interval,
+                        secondary_interval,
+                        print_commas: modifiers.colon,
+                        print_sign: modifiers.at && radix == 10,
+                        radix,
+                        prefix: modifiers.at && radix != 10,
+                        lowercase: directive.is_ascii_lowercase() && radix != 10,
+                        pad_after_sign,
+                    })
+                }
+                'F' => {
+                    let width = params.get_num_value(0, 0)?;
+                    let num_decimal_places = params.get_optional_num_value(1)?;
+                    params.assert_missing(2, "num digits parameter not supported for floats")?;
+                    params.assert_missing(3, "scale factor parameter not supported for floats")?;
+                    params.assert_missing(4, "overflow char parameter not supported for floats")?;
+                    let pad_char = params.get_char(5, ' ')?;
+                    let round_half_up = match params.get_num(6, 0)? {
+                        0 => false,
+                        1 => true,
+                        _ => return Err("the seventh parameter of `~F` must be 0 or 1".to_string()),
+                    };
+
+                    Ok(Directive::Float {
+                        width,
+                        num_decimal_places,
+                        pad_char,
+                        print_sign: modifiers.at,
+                        drop_leading_zero: modifiers.colon,
+                        round_half_up,
+                        left_justify: alternate.is_some(),
+                    })
+                }
+                'W' => Ok(Directive::Write {
+                    pretty: modifiers.colon,
+                }),
+                '%' => Ok(Directive::Newline {
+                    count: params.get_num_value(0, 1)?,
+                    crlf: modifiers.colon,
+                }),
+                '*' => {
+                    // `~@*` jumps to the first argument by default, the others move by one.
+                    let (direction, default) = if modifiers.at {
+                        (Navigation::Absolute, 0)
+                    } else if modifiers.colon {
+                        (Navigation::Backward, 1)
+                    } else {
+                        (Navigation::Forward, 1)
+                    };
+                    Ok(Directive::ArgNav {
+                        count: params.get_num(0, default)? as usize,
+                        direction,
+                    })
+                }
+                // `~n~` is `n` tildes. It becomes a literal, so a tilde in a block can't be
+                // taken for the end of the block: `~~}` is a tilde then a brace.
+                '~' => Ok(Directive::Literal(
+                    "~".repeat(params.get_num(0, 1)? as usize),
+                )),
+                '^' => {
+                    if state != State::Loop {
+                        Err("directive `^` not inside loop".to_string())
+                    } else {
+                        Ok(Directive::Break {
+                            outer: modifiers.colon,
+                            params: params.get_num_values(3)?,
+                        })
+                    }
+                }
+                ',' | '\'' | '#' | '0'..='9' if modifiers.colon || modifiers.at => {
+                    Err("parameters must come before the `:` and `@` modifiers".to_string())
+                }
+                directive => Err(format!("invalid directive `~{directive}`")),
+            },
+        )(input)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Param {
+    Char(char),
+    Num(isize),
+    /// `V` - take the parameter from the next argument.
+    FromArg,
+    /// `#` - the number of arguments remaining.
+    ArgCount,
+    Missing,
+}
+
+#[derive(Default, Debug, PartialEq, Eq)]
+struct Params {
+    parsed: Vec<Param>,
+}
+
+impl Params {
+    fn new(parsed: Vec<Param>) -> Self {
+        Self { parsed }
+    }
+
+    /// For parameters we don't support yet.
+    pub fn assert_missing(&self, idx: usize, msg: &str) -> Result<(), String> {
+        match self.parsed.get(idx) {
+            None | Some(Param::Missing) => Ok(()),
+            _ => Err(msg.to_string()),
+        }
+    }
+
+    /// For parameters that must be known when the format string is parsed.
+    pub fn get_num(&self, idx: usize, def: isize) -> Result<isize, String> {
+        match self.parsed.get(idx) {
+            Some(Param::Char(c)) => Err(format!("expected number, found char {c}")),
+            Some(Param::Num(i)) => Ok(*i),
+            Some(Param::FromArg) => Err("`V` parameter not supported here".to_string()),
+            Some(Param::ArgCount) => Err("`#` parameter not supported here".to_string()),
+            Some(Param::Missing) => Ok(def),
+            None => Ok(def),
+        }
+    }
+
+    pub fn get_char(&self, idx: usize, def: char) -> Result<char, String> {
+        match self.parsed.get(idx) {
+            Some(Param::Num(i)) => Err(format!("expected character, found number {i}")),
+            Some(Param::Char(c)) => Ok(*c),
+            Some(Param::FromArg) => Err("`V` parameter not supported here".to_string()),
+            Some(Param::ArgCount) => Err("expected character, found `#`".to_string()),
+            Some(Param::Missing) => Ok(def),
+            None => Ok(def),
+        }
+    }
+
+    /// For character parameters without a default.
+    pub fn get_optional_char(&self, idx: usize) -> Result<Option<char>, String> {
+        match self.parsed.get(idx) {
+            None | Some(Param::Missing) => Ok(None),
+            _ => Ok(Some(self.get_char(idx, ' ')?)),
+        }
+    }
+
+    /// For parameters without a default.
+    pub fn get_optional_num(&self, idx: usize) -> Result<Option<usize>, String> {
+        match self.parsed.get(idx) {
+            None | Some(Param::Missing) => Ok(None),
+            _ => Ok(Some(self.get_num(idx, 0)? as usize)),
+        }
+    }
+
+    /// For parameters that can be taken from the arguments with `V`.
+    pub fn get_num_value(&self, idx: usize, def: usize) -> Result<Value<usize>, String> {
+        match self.parsed.get(idx) {
+            Some(Param::FromArg) => Ok(Value::FromArg),
+            Some(Param::ArgCount) => Ok(Value::ArgCount),
+            _ => Ok(Value::Literal(self.get_num(idx, def as isize)? as usize)),
+        }
+    }
+
+    /// For directives that take up to `max` parameters, none of which can be omitted.
+    pub fn get_num_values(&self, max: usize) -> Result<Vec<Value<usize>>, String> {
+        let count = match self.parsed.as_slice() {
+            // A directive without parameters parses as a single missing one.
+            [Param::Missing] => 0,
+            parsed => parsed.len(),
+        };
+        if count > max {
+            return Err(format!("expected at most {max} parameters"));
+        }
+        (0..count)
+            .map(|idx| match self.parsed[idx] {
+                Param::Missing => Err("parameter cannot be omitted".to_string()),
+                _ => self.get_num_value(idx, 0),
+            })
+            .collect()
+    }
+
+    /// For parameters without a default that can be taken from the arguments with `V`.
+    pub fn get_optional_num_value(&self, idx: usize) -> Result<Option<Value<usize>>, String> {
+        match self.parsed.get(idx) {
+            None | Some(Param::Missing) => Ok(None),
+            _ => Ok(Some(self.get_num_value(idx, 0)?)),
+        }
+    }
+
+    pub fn get_char_value(&self, idx: usize, def: char) -> Result<Value<char>, String> {
+        match self.parsed.get(idx) {
+            Some(Param::FromArg) => Ok(Value::FromArg),
+            _ => Ok(Value::Literal(self.get_char(idx, def)?)),
+        }
+    }
+
+    pub fn get_optional_char_value(&self, idx: usize) -> Result<Option<Value<char>>, String> {
+        match self.parsed.get(idx) {
+            None | Some(Param::Missing) => Ok(None),
+            _ => Ok(Some(self.get_char_value(idx, ' ')?)),
+        }
+    }
+}
+
+/// Parses a single parameter either:
+/// -  an integer
+/// -  or a single character preceeded by a quote (')
+/// -  or `V`, meaning the parameter is taken from the next argument
+/// -  or `#`, meaning the number of arguments remaining, unless it comes right before `A`,
+///    `S` or `F`, where it is the `#` modifier
+fn param(input: &str) -> FormatResult<Param> {
+    alt((
+        map(preceded(tag("'"), anychar), Param::Char),
+        map(tag_no_case("v"), |_| Param::FromArg),
+        map(terminated(tag("#"), not(satisfy(takes_hash))), |_| {
+            Param::ArgCount
+        }),
+        map(digit1, |nums: &str| {
+            Param::Num(nums.parse().expect("numbers should have been parsed"))
+        }),
+        map(tag(""), |_: &str| Param::Missing),
+    ))(input)
+}
+
+/// Nums are parsed as numbers.
+/// Chars are preceeded with a quote `'`.
+fn params(input: &str) -> FormatResult<Params> {
+    map(separated_list0(tag(","), param), Params::new)(input)
+}
+
+/// Directives can be modified with a colon (`:`) or an ampersat (`@`).
+/// Modifiers have different meanings depending on the directive.
+struct Modifiers {
+    colon: bool,
+    at: bool,
+}
+
+/// Whether the directive takes the `#` modifier, which is written last, as in `~10:#A`.
+fn takes_hash(directive: char) -> bool {
+    matches!(directive.to_ascii_uppercase(), 'A' | 'S' | 'F')
+}
+
+fn modifiers(input: &str) -> FormatResult<Modifiers> {
+    let (input, modifiers) = take_while(|c| c == ':' || c == '@')(input)?;
+
+    Ok((
+        input,
+        Modifiers {
+            colon: modifiers.contains(':'),
+            at: modifiers.contains('@'),
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tilde_a() -> Directive {
+        Directive::TildeA {
+            padding: Padding::default(),
+            print_nil: false,
+            separator: None,
+            alternate: false,
+        }
+    }
+
+    fn newline() -> Directive {
+        Directive::Newline {
+            count: Value::Literal(1),
+            crlf: false,
+        }
+    }
+
+    #[test]
+    fn parses_newline_count() {
+        assert_eq!(
+            Ok(vec![
+                Directive::Newline {
+                    count: Value::Literal(3),
+                    crlf: false,
+                },
+                Directive::Newline {
+                    count: Value::FromArg,
+                    crlf: false,
+                },
+                newline(),
+                Directive::Newline {
+                    count: Value::Literal(2),
+                    crlf: true,
+                },
+            ]),
+            parse_format_string("~3%~V%~%~2:%").map_err(|err| err.message)
+        );
+    }
+
+    #[test]
+    fn parses_literal() {
+        assert_eq!(
+            ("", Directive::Literal("zork".to_string())),
+            literal("zork").unwrap()
+        );
+        assert_eq!(
+            ("~A", Directive::Literal("zork".to_string())),
+            literal("zork~A").unwrap()
+        );
+    }
+
+    #[test]
+    fn parses_params() {
+        assert_eq!(
+            (
+                "a",
+                Params {
+                    parsed: vec![Param::Num(42)]
+                }
+            ),
+            params("42a").unwrap()
+        );
+
+        assert_eq!(
+            (
+                "a",
+                Params {
+                    parsed: vec![Param::Num(42), Param::Char(' ')]
+                }
+            ),
+            params("42,' a").unwrap()
+        );
+
+        assert_eq!(
+            (
+                "a",
+                Params {
+                    parsed: vec![Param::Num(42), Param::Char(' '), Param::Num(234)]
+                }
+            ),
+            params("42,' ,234a").unwrap()
+        );
+    }
+
+    #[test]
+    fn parses() {
+        let format_string = "Hello, ~A! Value: ~D~%";
+        let parsed = parse_format_string(format_string).unwrap();
+
+        assert_eq!(
+            vec![
+                Directive::Literal("Hello, ".to_string()),
+                tilde_a(),
+                Directive::Literal("! Value: ".to_string()),
+                Directive::Decimal {
+                    min_columns: Value::Literal(0),
+                    pad_char: Value::Literal(' '),
+                    comma_char: None,
+                    comma_interval: None,
+                    secondary_interval: None,
+                    print_commas: false,
+                    print_sign: false,
+                    radix: 10,
+                    prefix: false,
+                    lowercase: false,
+                    pad_after_sign: false,
+                },
+                newline()
+            ],
+            parsed
+        );
+    }
+
+    #[test]
+    fn parses_tilde_a_padding() {
+        let format_string = "~10,2,1,'*A";
+        let parsed = parse_format_string(format_string).unwrap();
+
+        assert_eq!(
+            vec![Directive::TildeA {
+                padding: Padding {
+                    min_columns: Value::Literal(10),
+                    col_inc: Value::Literal(2),
+                    min_pad: Value::Literal(1),
+                    pad_char: Value::Literal('*'),
+                    max_columns: None,
+                    pad_left: false,
+                },
+                print_nil: false,
+                separator: None,
+                alternate: false,
+            }],
+            parsed
+        );
+
+        assert_eq!(
+            Ok(vec![Directive::TildeA {
+                padding: Padding::default(),
+                print_nil: true,
+                separator: Some(','),
+                alternate: false,
+            }]),
+            parse_format_string("~,,,,,',:A").map_err(|err| err.message)
+        );
+        assert_eq!(
+            Err("only `~:A` takes a separator".to_string()),
+            parse_format_string("~,,,,,',A").map_err(|err| err.message)
+        );
+
+        let format_string = "~,,,'.,10A";
+        let parsed = parse_format_string(format_string).unwrap();
+
+        assert_eq!(
+            vec![Directive::TildeA {
+                padding: Padding {
+                    pad_char: Value::Literal('.'),
+                    max_columns: Some(Value::Literal(10)),
+                    ..Default::default()
+                },
+                print_nil: false,
+                separator: None,
+                alternate: false,
+            }],
+            parsed
+        );
+
+        let format_string = "~10@A";
+        let parsed = parse_format_string(format_string).unwrap();
+
+        assert_eq!(
+            vec![Directive::TildeA {
+                padding: Padding {
+                    min_columns: Value::Literal(10),
+                    pad_left: true,
+                    ..Default::default()
+                },
+                print_nil: false,
+                separator: None,
+                alternate: false,
+            }],
+            parsed
+        );
+    }
+
+    #[test]
+    fn parses_the_alternate_modifier() {
+        assert_eq!(
+            Ok(vec![Directive::TildeA {
+                padding: Padding::default(),
+                print_nil: false,
+                separator: None,
+                alternate: true,
+            }]),
+            parse_format_string("~#A").map_err(|err| err.message)
+        );
+        assert_eq!(
+            Ok(vec![Directive::TildeS {
+                padding: Padding {
+                    min_columns: Value::Literal(10),
+                    pad_left: true,
+                    ..Default::default()
+                },
+                alternate: true,
+            }]),
+            parse_format_string("~10@#s").map_err(|err| err.message)
+        );
+
+        // Followed by a comma or a modifier, `#` is still the number of arguments remaining.
+        assert_eq!(
+            Ok(vec![Directive::TildeA {
+                padding: Padding {
+                    min_columns: Value::ArgCount,
+                    ..Default::default()
+                },
+                print_nil: false,
+                separator: None,
+                alternate: false,
+            }]),
+            parse_format_string("~#,A").map_err(|err| err.message)
+        );
+        assert_eq!(
+            Ok(vec![Directive::TildeA {
+                padding: Padding {
+                    min_columns: Value::ArgCount,
+                    ..Default::default()
+                },
+                print_nil: true,
+                separator: None,
+                alternate: true,
+            }]),
+            parse_format_string("~#:#A").map_err(|err| err.message)
+        );
+
+        assert_eq!(
+            Err("only `~A`, `~S` and `~F` take the `#` modifier".to_string()),
+            parse_format_string("~:#D").map_err(|err| err.message)
+        );
+    }
+
+    #[test]
+    fn parses_tildes() {
+        assert_eq!(
+            Ok(vec![Directive::Literal("~~~~}".to_string())]),
+            parse_format_string("~~~3~}").map_err(|err| err.message)
+        );
+        assert_eq!(
+            Ok(vec![Directive::Iteration {
+                sublists: false,
+                remaining: false,
+                max_passes: None,
+                empty: None,
+                directives: vec![Directive::Literal("~}".to_string())],
+            }]),
+            parse_format_string("~{~~}~}").map_err(|err| err.message)
+        );
+        assert_eq!(
+            Err("unterminated `~{` directive, expected a closing `~}`".to_string()),
+            parse_format_string("~{~~}").map_err(|err| err.message)
+        );
+    }
+
+    #[test]
+    fn merges_literals() {
+        assert_eq!(
+            Ok(vec![Directive::Literal("zork ~ nork".to_string())]),
+            parse_format_string("zork ~~ nork").map_err(|err| err.message)
+        );
+        assert_eq!(
+            Ok(vec![Directive::Conditional {
+                boolean: true,
+                consumes: false,
+                selector: None,
+                default: None,
+                choices: vec![
+                    vec![Directive::Literal("~zork".to_string())],
+                    vec![Directive::Literal("nork~".to_string())],
+                ],
+            }]),
+            parse_format_string("~:[~~zork~;nork~~~]").map_err(|err| err.message)
+        );
+    }
+
+    #[test]
+    fn parses_iteration() {
+        let format_string = "Hello, ~{~Anork~A~}~%";
+        let parsed = parse_format_string(format_string).unwrap();
+
+        assert_eq!(
+            vec![
+                Directive::Literal("Hello, ".to_string()),
+                Directive::Iteration {
+                    sublists: false,
+                    remaining: false,
+                    max_passes: None,
+                    directives: vec![tilde_a(), Directive::Literal("nork".to_string()), tilde_a(),],
+                    empty: None,
+                },
+                newline()
+            ],
+            parsed
+        );
+    }
+
+    #[test]
+    fn parses_sublist_iteration() {
+        let format_string = "~:{~A=~A~}";
+        let parsed = parse_format_string(format_string).unwrap();
+
+        assert_eq!(
+            vec![Directive::Iteration {
+                sublists: true,
+                remaining: false,
+                max_passes: None,
+                directives: vec![tilde_a(), Directive::Literal("=".to_string()), tilde_a()],
+                empty: None,
+            }],
+            parsed
+        );
+    }
+
+    #[test]
+    fn parses_remaining_iteration() {
+        let format_string = "~@{~A~}";
+        let parsed = parse_format_string(format_string).unwrap();
+
+        assert_eq!(
+            vec![Directive::Iteration {
+                sublists: false,
+                remaining: true,
+                max_passes: None,
+                directives: vec![tilde_a()],
+                empty: None,
+            }],
+            parsed
+        );
+    }
+
+    #[test]
+    fn parses_outer_break() {
+        let format_string = "~{~A~:^~}";
+        let parsed = parse_format_string(format_string).unwrap();
+
+        assert_eq!(
+            vec![Directive::Iteration {
+                sublists: false,
+                remaining: false,
+                max_passes: None,
+                directives: vec![
+                    tilde_a(),
+                    Directive::Break {
+                        outer: true,
+                        params: vec![]
+                    }
+                ],
+                empty: None,
+            }],
+            parsed
+        );
+    }
+
+    #[test]
+    fn parses_break_parameters() {
+        let parsed = parse_format_string("~{~1,V,#^~}").unwrap();
+        assert_eq!(
+            vec![Directive::Iteration {
+                sublists: false,
+                remaining: false,
+                max_passes: None,
+                directives: vec![Directive::Break {
+                    outer: false,
+                    params: vec![Value::Literal(1), Value::FromArg, Value::ArgCount],
+                }],
+                empty: None,
+            }],
+            parsed
+        );
+
+        assert!(parse_format_string("~{~1,2,3,4^~}").is_err());
+        assert!(parse_format_string("~{~,2^~}").is_err());
+    }
+
+    #[test]
+    fn parses_max_passes() {
+        let format_string = "~2{~A~}";
+        let parsed = parse_format_string(format_string).unwrap();
+
+        assert_eq!(
+            vec![Directive::Iteration {
+                sublists: false,
+                remaining: false,
+                max_passes: Some(2),
+                directives: vec![tilde_a()],
+                empty: None,
+            }],
+            parsed
+        );
+
+        let parsed = parse_format_string("~'a{~A~}").map_err(|err| err.message);
+        assert_eq!(Err("expected number, found char a".to_string()), parsed);
+    }
+
+    #[test]
+    fn errors_on_empty_sublist_iteration() {
+        let parsed = parse_format_string("~:{~}").map_err(|err| err.message);
+        assert_eq!(
+            Err("`~:{~}` and `~@{~}` must have a body".to_string()),
+            parsed
+        );
+    }
+
+    #[test]
+    fn parses_the_empty_part_of_iteration() {
+        assert_eq!(
+            Ok(vec![Directive::Iteration {
+                sublists: false,
+                remaining: false,
+                max_passes: None,
+                directives: vec![tilde_a(), Directive::Literal(" ".to_string())],
+                empty: Some(vec![Directive::Literal("none".to_string()), newline()]),
+            }]),
+            parse_format_string("~{~A ~:;none~%~}").map_err(|err| err.message)
+        );
+        assert_eq!(
+            Ok(vec![Directive::Iteration {
+                sublists: true,
+                remaining: false,
+                max_passes: None,
+                directives: vec![tilde_a()],
+                empty: Some(vec![]),
+            }]),
+            parse_format_string("~:{~A~:;~}").map_err(|err| err.message)
+        );
+
+        // A `~:;` inside a conditional belongs to it.
+        let parsed = parse_format_string("~{~[a~:;b~]~}").unwrap();
+        assert!(matches!(
+            parsed.as_slice(),
+            [Directive::Iteration { empty: None, .. }]
+        ));
+
+        assert_eq!(
+            Err("`~{` must have a body before `~:;`".to_string()),
+            parse_format_string("~{~:;none~}").map_err(|err| err.message)
+        );
+        assert_eq!(
+            Err("`~{` can only have one `~:;`".to_string()),
+            parse_format_string("~{~A~:;none~:;nothing~}").map_err(|err| err.message)
+        );
+    }
+
+    #[test]
+    fn errors_on_unterminated_blocks() {
+        assert_eq!(
+            Err("unterminated `~<` directive, expected a closing `~>`".to_string()),
+            parse_format_string("~<~A~;~A").map_err(|err| err.message)
+        );
+        assert_eq!(
+            Err("unterminated `~{` directive, expected a closing `~}`".to_string()),
+            parse_format_string("~{~A~^, ").map_err(|err| err.message)
+        );
+        assert_eq!(
+            Err("unterminated `~[` directive, expected a closing `~]`".to_string()),
+            parse_format_string("~[zero~;one").map_err(|err| err.message)
+        );
+        assert_eq!(
+            Err("unterminated `~{` directive, expected a closing `~}`".to_string()),
+            parse_format_string("~{~<~A~>").map_err(|err| err.message)
+        );
+    }
+
+    #[test]
+    fn parses_alignment() {
+        let format_string = "zork ~10<~A~>~%";
+        let parsed = parse_format_string(format_string).unwrap();
+
+        assert_eq!(
+            vec![
+                Directive::Literal("zork ".to_string()),
+                Directive::Align {
+                    segments: vec![vec![tilde_a()]],
+                    min_columns: Value::Literal(10),
+                    col_inc: 0,
+                    min_pad: 0,
+                    pad_char: Value::Literal(' '),
+                    direction: Alignment::Justify,
+                    overflow: None,
+                },
+                newline(),
+            ],
+            parsed
+        );
+    }
+
+    #[test]
+    fn parses_right_alignment() {
+        let format_string = "zork ~10:<~A~>~%";
+        let parsed = parse_format_string(format_string).unwrap();
+
+        assert_eq!(
+            vec![
+                Directive::Literal("zork ".to_string()),
+                Directive::Align {
+                    segments: vec![vec![tilde_a()]],
+                    min_columns: Value::Literal(10),
+                    col_inc: 0,
+                    min_pad: 0,
+                    pad_char: Value::Literal(' '),
+                    direction: Alignment::Right,
+                    overflow: None,
+                },
+                newline(),
+            ],
+            parsed
+        );
+    }
+
+    #[test]
+    fn parses_centre_alignment() {
+        let format_string = "zork ~10:@<~A~>~%";
+        let parsed = parse_format_string(format_string).unwrap();
+
+        assert_eq!(
+            vec![
+                Directive::Literal("zork ".to_string()),
+                Directive::Align {
+                    segments: vec![vec![tilde_a()]],
+                    min_columns: Value::Literal(10),
+                    col_inc: 0,
+                    min_pad: 0,
+                    pad_char: Value::Literal(' '),
+                    direction: Alignment::Centre,
+                    overflow: None,
+                },
+                newline(),
+            ],
+            parsed
+        );
+    }
+
+    #[test]
+    fn parses_alignment_segments() {
+        let format_string = "~20@<zork~;~A~;nork~>";
+        let parsed = parse_format_string(format_string).unwrap();
+
+        assert_eq!(
+            vec![Directive::Align {
+                segments: vec![
+                    vec![Directive::Literal("zork".to_string())],
+                    vec![tilde_a()],
+                    vec![Directive::Literal("nork".to_string())],
+                ],
+                min_columns: Value::Literal(20),
+                col_inc: 0,
+                min_pad: 0,
+                pad_char: Value::Literal(' '),
+                direction: Alignment::Left,
+                overflow: None,
+            }],
+            parsed
+        );
+    }
+
+    #[test]
+    fn parses_alignment_overflow() {
+        let format_string = "~<~%;; ~1,30:;~A~>";
+        let parsed = parse_format_string(format_string).unwrap();
+
+        assert_eq!(
+            vec![Directive::Align {
+                segments: vec![vec![tilde_a()]],
+                min_columns: Value::Literal(0),
+                col_inc: 0,
+                min_pad: 0,
+                pad_char: Value::Literal(' '),
+                direction: Alignment::Justify,
+                overflow: Some(Overflow {
+                    spare: 1,
+                    line_width: 30,
+                    directives: vec![newline(), Directive::Literal(";; ".to_string())],
+                }),
+            }],
+            parsed
+        );
+
+        let parsed = parse_format_string("~<~:;~A~>").unwrap();
+        assert!(matches!(
+            &parsed[0],
+            Directive::Align {
+                overflow: Some(Overflow {
+                    spare: 0,
+                    line_width: 72,
+                    ..
+                }),
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn errors_on_late_overflow() {
+        let format_string = "~<~A~;~:;~A~>";
+        let parsed = parse_format_string(format_string).map_err(|err| err.message);
+        assert_eq!(
+            Err("only the first segment can be terminated by `~:;`".to_string()),
+            parsed
+        );
+    }
+
+    #[test]
+    fn parse_conditional() {
+        let format_string = "~[zork~;zoggle~;zoog~]";
+        let parsed = parse_format_string(format_string).unwrap();
+        assert_eq!(
+            vec![Directive::Conditional {
+                boolean: false,
+                consumes: false,
+                selector: None,
+                default: None,
+                choices: vec![
+                    vec![Directive::Literal("zork".to_string())],
+                    vec![Directive::Literal("zoggle".to_string())],
+                    vec![Directive::Literal("zoog".to_string())],
+                ]
+            }],
+            parsed
+        );
+    }
+
+    #[test]
+    fn parse_conditional_with_default() {
+        let format_string = "~[zork~;zoggle~:;zoog~]";
+        let parsed = parse_format_string(format_string).unwrap();
+        assert_eq!(
+            vec![Directive::Conditional {
+                boolean: false,
+                consumes: false,
+                selector: None,
+                choices: vec![
+                    vec![Directive::Literal("zork".to_string())],
+                    vec![Directive::Literal("zoggle".to_string())],
+                ],
+                default: Some(vec![Directive::Literal("zoog".to_string())]),
+            }],
+            parsed
+        );
+    }
+
+    #[test]
+    fn parse_conditional_selector() {
+        let format_string = "~#[none~;one~:;many~]";
+        let parsed = parse_format_string(format_string).unwrap();
+        assert_eq!(
+            vec![Directive::Conditional {
+                boolean: false,
+                consumes: false,
+                selector: Some(Value::ArgCount),
+                choices: vec![
+                    vec![Directive::Literal("none".to_string())],
+                    vec![Directive::Literal("one".to_string())],
+                ],
+                default: Some(vec![Directive::Literal("many".to_string())]),
+            }],
+            parsed
+        );
+
+        let parsed = parse_format_string("~1[a~;b~;c~]").unwrap();
+        assert_eq!(
+            vec![Directive::Conditional {
+                boolean: false,
+                consumes: false,
+                selector: Some(Value::Literal(1)),
+                choices: vec![
+                    vec![Directive::Literal("a".to_string())],
+                    vec![Directive::Literal("b".to_string())],
+                    vec![Directive::Literal("c".to_string())],
+                ],
+                default: None,
+            }],
+            parsed
+        );
+
+        let parsed = parse_format_string("~5[a~;b~]").map_err(|err| err.message);
+        assert_eq!(
+            Err("`~5[` selects choice 5, but there are only 2 choices".to_string()),
+            parsed
+        );
+
+        // The default is taken for any other index, and an empty last choice still counts.
+        assert!(parse_format_string("~5[a~;b~:;c~]").is_ok());
+        assert!(parse_format_string("~1[a~;~]").is_ok());
+
+        let parsed = parse_format_string("~#:[yes~;no~]").map_err(|err| err.message);
+        assert_eq!(
+            Err("`~:[` and `~@[` do not take a parameter".to_string()),
+            parsed
+        );
+    }
+
+    #[test]
+    fn parses_radix() {
+        let parsed = parse_format_string("~8,'0X~@o~:B~x").unwrap();
+        let based =
+            |min_columns, pad_char, print_commas, radix, prefix, lowercase| Directive::Decimal {
+                min_columns: Value::Literal(min_columns),
+                pad_char: Value::Literal(pad_char),
+                comma_char: None,
+                comma_interval: None,
+                secondary_interval: None,
+                print_commas,
+                print_sign: false,
+                radix,
+                prefix,
+                lowercase,
+                pad_after_sign: false,
+            };
+        assert_eq!(
+            vec![
+                based(8, '0', false, 16, false, false),
+                based(0, ' ', false, 8, true, true),
+                based(0, ' ', true, 2, false, false),
+                based(0, ' ', false, 16, false, true),
+            ],
+            parsed
+        );
+    }
+
+    #[test]
+    fn parses_pad_after_sign() {
+        let parsed = parse_format_string("~6,,,,,1D~6d").unwrap();
+        let decimal = |pad_after_sign| Directive::Decimal {
+            min_columns: Value::Literal(6),
+            pad_char: Value::Literal(' '),
+            comma_char: None,
+            comma_interval: None,
+            secondary_interval: None,
+            print_commas: false,
+            print_sign: false,
+            radix: 10,
+            prefix: false,
+            lowercase: false,
+            pad_after_sign,
+        };
+        assert_eq!(vec![decimal(true), decimal(false)], parsed);
+
+        let parsed = parse_format_string("~6,,,,,2D").map_err(|err| err.message);
+        assert_eq!(
+            Err("the sixth parameter of `~D` must be 0 or 1".to_string()),
+            parsed
+        );
+    }
+
+    #[test]
+    fn parses_float() {
+        assert_eq!(
+            Ok(vec![Directive::Float {
+                width: Value::Literal(0),
+                num_decimal_places: None,
+                pad_char: ' ',
+                print_sign: false,
+                drop_leading_zero: false,
+                round_half_up: false,
+                left_justify: false,
+            }]),
+            parse_format_string("~F").map_err(|err| err.message)
+        );
+        assert_eq!(
+            Ok(vec![Directive::Float {
+                width: Value::Literal(6),
+                num_decimal_places: Some(Value::Literal(0)),
+                pad_char: ' ',
+                print_sign: false,
+                drop_leading_zero: false,
+                round_half_up: false,
+                left_justify: false,
+            }]),
+            parse_format_string("~6,0F").map_err(|err| err.message)
+        );
+        assert_eq!(
+            Ok(vec![Directive::Float {
+                width: Value::Literal(0),
+                num_decimal_places: Some(Value::Literal(2)),
+                pad_char: ' ',
+                print_sign: true,
+                drop_leading_zero: false,
+                round_half_up: false,
+                left_justify: false,
+            }]),
+            parse_format_string("~,2@F").map_err(|err| err.message)
+        );
+        assert_eq!(
+            Ok(vec![Directive::Float {
+                width: Value::Literal(10),
+                num_decimal_places: Some(Value::Literal(2)),
+                pad_char: ' ',
+                print_sign: true,
+                drop_leading_zero: false,
+                round_half_up: false,
+                left_justify: true,
+            }]),
+            parse_format_string("~10,2@#F").map_err(|err| err.message)
+        );
+        assert_eq!(
+            Ok(vec![Directive::Float {
+                width: Value::Literal(0),
+                num_decimal_places: Some(Value::Literal(2)),
+                pad_char: ' ',
+                print_sign: false,
+                drop_leading_zero: false,
+                round_half_up: true,
+                left_justify: false,
+            }]),
+            parse_format_string("~,2,,,,,1F").map_err(|err| err.message)
+        );
+        assert_eq!(
+            Err("the seventh parameter of `~F` must be 0 or 1".to_string()),
+            parse_format_string("~,2,,,,,3F").map_err(|err| err.message)
+        );
+    }
+
+    #[test]
+    fn parses_argument_navigation() {
+        let nav = |count, direction| Directive::ArgNav { count, direction };
+        let cases = [
+            ("~*", nav(1, Navigation::Forward)),
+            ("~2*", nav(2, Navigation::Forward)),
+            ("~0*", nav(0, Navigation::Forward)),
+            ("~:*", nav(1, Navigation::Backward)),
+            ("~3:*", nav(3, Navigation::Backward)),
+            ("~@*", nav(0, Navigation::Absolute)),
+            ("~2@*", nav(2, Navigation::Absolute)),
+        ];
+        for (format_string, directive) in cases {
+            assert_eq!(
+                Ok(vec![directive]),
+                parse_format_string(format_string).map_err(|err| err.message),
+                "{format_string}"
+            );
+        }
+
+        let parsed = parse_format_string("~A~:*").unwrap();
+        assert_eq!(vec![tilde_a(), nav(1, Navigation::Backward)], parsed);
+    }
+
+    #[test]
+    fn parses_write() {
+        let format_string = "~W~:W";
+        let parsed = parse_format_string(format_string).unwrap();
+        assert_eq!(
+            vec![
+                Directive::Write { pretty: false },
+                Directive::Write { pretty: true },
+            ],
+            parsed
+        );
+    }
+
+    #[test]
+    fn parses_captured() {
+        let format_string = "~`name`A~`point.x`10D";
+        let parsed = parse_format_string(format_string).unwrap();
+        assert_eq!(
+            vec![
+                Directive::Captured {
+                    name: "name".to_string(),
+                    directive: Box::new(tilde_a()),
+                },
+                Directive::Captured {
+                    name: "point.x".to_string(),
+                    directive: Box::new(Directive::Decimal {
+                        min_columns: Value::Literal(10),
+                        pad_char: Value::Literal(' '),
+                        comma_char: None,
+                        comma_interval: None,
+                        secondary_interval: None,
+                        print_commas: false,
+                        print_sign: false,
+                        radix: 10,
+                        prefix: false,
+                        lowercase: false,
+                        pad_after_sign: false,
+                    }),
+                },
+            ],
+            parsed
+        );
+    }
+
+    #[test]
+    fn errors_on_invalid_captured() {
+        assert_eq!(
+            Err("invalid captured variable `point.`".to_string()),
+            parse_format_string("~`point.`A").map_err(|err| err.message)
+        );
+        assert_eq!(
+            Err("invalid captured variable `1`".to_string()),
+            parse_format_string("~`1`A").map_err(|err| err.message)
+        );
+        assert_eq!(
+            Err("directive cannot use the captured variable `name`".to_string()),
+            parse_format_string("~`name`%").map_err(|err| err.message)
+        );
+        assert_eq!(
+            Err("`V` parameter cannot be used with a captured variable".to_string()),
+            parse_format_string("~`name`VA").map_err(|err| err.message)
+        );
+    }
+
+    #[test]
+    fn errors_on_invalid_directive() {
+        let format_string = "Ook, ~z";
+        let parsed = parse_format_string(format_string).map_err(|err| err.message);
+        assert_eq!(Err("invalid directive `~Z`".to_string()), parsed);
+    }
+
+    #[test]
+    fn errors_on_modifiers_before_parameters() {
+        assert_eq!(
+            Err("parameters must come before the `:` and `@` modifiers".to_string()),
+            parse_format_string("~@,2F").map_err(|err| err.message)
+        );
+        assert_eq!(
+            Err("parameters must come before the `:` and `@` modifiers".to_string()),
+            parse_format_string("~:3D").map_err(|err| err.message)
+        );
+    }
+
+    #[test]
+    fn reports_error_position() {
+        let position = |format_string| parse_format_string(format_string).unwrap_err().position;
+        assert_eq!(5, position("Ook, ~z"));
+        assert_eq!(8, position("~{~A~^, ~5z~}"));
+        assert_eq!(3, position("~A ~#:[yes~;no~]"));
+        assert_eq!(3, position("~A ~{ ~A"));
+    }
+
+    #[test]
+    fn parses_loop_index() {
+        assert_eq!(
+            Ok(vec![Directive::Iteration {
+                sublists: false,
+                remaining: false,
+                max_passes: None,
+                directives: vec![
+                    Directive::LoopIndex,
+                    Directive::Literal(": ".to_string()),
+                    tilde_a(),
+                    Directive::LoopIndex,
+                ],
+                empty: None,
+            }]),
+            parse_format_string("~{~#: ~A~#~}").map_err(|err| err.message)
+        );
+        // Followed by a directive character, `#` is its parameter.
+        assert_eq!(
+            Ok(vec![Directive::Iteration {
+                sublists: false,
+                remaining: false,
+                max_passes: None,
+                directives: vec![Directive::Newline {
+                    count: Value::ArgCount,
+                    crlf: false,
+                }],
+                empty: None,
+            }]),
+            parse_format_string("~{~#%~}").map_err(|err| err.message)
+        );
+        assert_eq!(
+            Err("directive `#` not inside loop".to_string()),
+            parse_format_string("~#: ~A").map_err(|err| err.message)
+        );
+        assert_eq!(
+            Err("invalid directive `~Q`".to_string()),
+            parse_format_string("~{~#Q~}").map_err(|err| err.message)
+        );
+    }
+
+    #[test]
+    fn errors_on_break_outside_loop() {
+        let format_string = "Oook ~^ ~{~A}";
+        let parsed = parse_format_string(format_string).map_err(|err| err.message);
+        assert_eq!(Err("directive `^` not inside loop".to_string()), parsed);
+    }
+
+    #[test]
+    fn parse_params() {
+        let (_, res) = params("3,2,3").unwrap();
+        assert_eq!(
+            vec![Param::Num(3), Param::Num(2), Param::Num(3)],
+            res.parsed
+        );
+    }
+
+    #[test]
+    fn parse_params_from_args() {
+        let (_, res) = params("V,'*,v").unwrap();
+        assert_eq!(
+            vec![Param::FromArg, Param::Char('*'), Param::FromArg],
+            res.parsed
+        );
+
+        let format_string = "~V,'*D";
+        let parsed = parse_format_string(format_string).unwrap();
+        assert_eq!(
+            vec![Directive::Decimal {
+                min_columns: Value::FromArg,
+                pad_char: Value::Literal('*'),
+                comma_char: None,
+                comma_interval: None,
+                secondary_interval: None,
+                print_commas: false,
+                print_sign: false,
+                radix: 10,
+                prefix: false,
+                lowercase: false,
+                pad_after_sign: false,
+            }],
+            parsed
+        );
+    }
+
+    #[test]
+    fn parse_secondary_interval_of_zero() {
+        let parsed = parse_format_string("~,,,3,0:D").map_err(|err| err.message);
+        assert_eq!(
+            Err("the interval between commas after the first group cannot be 0".to_string()),
+            parsed
+        );
+    }
+
+    #[test]
+    fn parse_params_arg_count() {
+        let (_, res) = params("#,'0").unwrap();
+        assert_eq!(vec![Param::ArgCount, Param::Char('0')], res.parsed);
+
+        let format_string = "~,#D";
+        let parsed = parse_format_string(format_string).map_err(|err| err.message);
+        assert_eq!(Err("expected character, found `#`".to_string()), parsed);
+    }
+
+    #[test]
+    fn parse_missing_params() {
+        let (_, res) = params("3,,3").unwrap();
+        assert_eq!(
+            vec![Param::Num(3), Param::Missing, Param::Num(3)],
+            res.parsed
+        );
+
+        let (_, res) = params(",9,3").unwrap();
+        assert_eq!(
+            vec![Param::Missing, Param::Num(9), Param::Num(3)],
+            res.parsed
+        );
+
+        let (_, res) = params(",,3").unwrap();
+        assert_eq!(
+            vec![Param::Missing, Param::Missing, Param::Num(3)],
+            res.parsed
+        );
+    }
+}
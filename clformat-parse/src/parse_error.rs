@@ -0,0 +1,109 @@
+//! An error struct that makes it easier for us to report the relevant errors while parsing.
+use std::ops::Deref;
+
+use nom::error::{FromExternalError, ParseError as NomParseError};
+
+/// Describes why a format string could not be parsed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    /// What is wrong with the format string.
+    pub message: String,
+    /// The byte offset in the format string of the directive that could not be parsed.
+    pub position: usize,
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Clone, Debug)]
+pub(crate) struct FormatError<I> {
+    input: I,
+    error: ErrorType,
+}
+
+#[derive(Clone, Debug)]
+enum ErrorType {
+    /// The error has come from Nom. Ideally we shouldn't get to the stage where we report
+    /// these errors.
+    Nom,
+    /// The error has come from us wrapping the parser with `map_res` to define our own error
+    /// message.
+    Ours(String),
+}
+
+impl<I> FormatError<I> {
+    /// Moves the error to `input`, such as the start of the directive it occurred in.
+    pub(crate) fn at(self, input: I) -> Self {
+        Self { input, ..self }
+    }
+}
+
+impl<T> FormatError<T>
+where
+    T: Deref<Target = str>,
+{
+    /// Returns the position in the input string that this error starts.
+    /// Assumes the the input string in the error message is the string from the point
+    /// the error occurred up to the end of the format string.
+    pub(crate) fn error_pos(&self, input: T) -> usize {
+        input.deref().len() - self.input.deref().len()
+    }
+}
+
+impl<I> NomParseError<I> for FormatError<I>
+where
+    I: Deref<Target = str>,
+{
+    fn from_error_kind(input: I, _kind: nom::error::ErrorKind) -> Self {
+        Self {
+            input,
+            error: ErrorType::Nom,
+        }
+    }
+
+    fn append(_input: I, _kind: nom::error::ErrorKind, other: Self) -> Self {
+        // Keep the input where the error occurred rather than where the enclosing parser
+        // started, so the error points at the offending directive.
+        other
+    }
+
+    fn or(self, other: Self) -> Self {
+        // When none of the alternatives match, report our own error from the one that got
+        // furthest.
+        match (&self.error, &other.error) {
+            (ErrorType::Ours(_), ErrorType::Nom) => self,
+            (ErrorType::Nom, ErrorType::Ours(_)) => other,
+            _ if self.input.len() < other.input.len() => self,
+            _ => other,
+        }
+    }
+}
+
+impl<I, E> FromExternalError<I, E> for FormatError<I>
+where
+    E: ToString,
+{
+    fn from_external_error(input: I, _kind: nom::error::ErrorKind, e: E) -> Self {
+        Self {
+            input,
+            error: ErrorType::Ours(e.to_string()),
+        }
+    }
+}
+
+impl<I> std::fmt::Display for FormatError<I>
+where
+    I: Deref<Target = str>,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.error {
+            ErrorType::Nom => write!(f, "internal error"),
+            ErrorType::Ours(error) => write!(f, "{error}"),
+        }
+    }
+}
@@ -0,0 +1,41 @@
+//! Times `~D` with and without commas. Run with `cargo bench -p clformat`, and compare with
+//! `--features fast-num`.
+use std::fmt::Write;
+use std::hint::black_box;
+use std::time::Instant;
+
+use clformat::clformat;
+
+const ITERATIONS: u32 = 1_000_000;
+
+fn bench(name: &str, mut format: impl FnMut(&mut String, i64)) {
+    let mut output = String::new();
+    let start = Instant::now();
+    for n in 0..ITERATIONS {
+        output.clear();
+        format(&mut output, black_box(i64::from(n) * 7_919 - 4_000_000));
+    }
+    let elapsed = start.elapsed();
+    println!(
+        "{name:<12} {:>8.1} ns/iter",
+        elapsed.as_nanos() as f64 / f64::from(ITERATIONS)
+    );
+}
+
+fn main() {
+    bench("~D", |output, n| {
+        clformat!(*output, "~D", n);
+    });
+    bench("~:D", |output, n| {
+        clformat!(*output, "~:D", n);
+    });
+    bench("~12D", |output, n| {
+        clformat!(*output, "~12D", n);
+    });
+    bench("~:D chars", |output, n| {
+        for c in clformat::Decimal::new(0, ' ', ',', 3, true, false, n) {
+            output.write_char(c).unwrap();
+        }
+    });
+    bench("{}", |output, n| write!(output, "{n}").unwrap());
+}
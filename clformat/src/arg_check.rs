@@ -0,0 +1,89 @@
+//! Checks the macro makes on its arguments so that a missing trait names the directive that
+//! needs it and points at the argument, rather than at the whole macro.
+use core::fmt::{Debug, Display};
+
+#[doc(hidden)]
+#[diagnostic::on_unimplemented(
+    message = "`~A` requires an argument that implements `Display`",
+    label = "this argument is formatted by `~A`",
+    note = "use `~S` to format the argument with `Debug`"
+)]
+pub trait DisplayArg {}
+
+impl<T: Display + ?Sized> DisplayArg for T {}
+
+#[doc(hidden)]
+#[diagnostic::on_unimplemented(
+    message = "`~S` and `~W` require an argument that implements `Debug`",
+    label = "this argument is formatted by `~S` or `~W`"
+)]
+pub trait DebugArg {}
+
+impl<T: Debug + ?Sized> DebugArg for T {}
+
+#[doc(hidden)]
+pub fn display_arg<T: DisplayArg + ?Sized>(_: &T) {}
+
+#[doc(hidden)]
+pub fn debug_arg<T: DebugArg + ?Sized>(_: &T) {}
+
+#[doc(hidden)]
+#[diagnostic::on_unimplemented(
+    message = "`~F` requires an argument that implements `Display`, such as a float",
+    label = "this argument is formatted by `~F`"
+)]
+pub trait FloatArg {}
+
+impl<T: Display> FloatArg for T {}
+
+#[doc(hidden)]
+pub fn float_arg<T: FloatArg>(value: T) -> T {
+    value
+}
+
+#[doc(hidden)]
+#[diagnostic::on_unimplemented(
+    message = "`~{{` requires an argument that implements `IntoIterator`",
+    label = "this argument is iterated by `~{{`",
+    note = "use `clformat::Times` to repeat the body a number of times"
+)]
+pub trait ListArg {}
+
+impl<T: IntoIterator> ListArg for T {}
+
+#[doc(hidden)]
+pub fn list_arg<T: ListArg>(list: T) -> T {
+    list
+}
+
+#[doc(hidden)]
+#[diagnostic::on_unimplemented(
+    message = "`~@[` requires an `Option` argument",
+    label = "this argument is tested by `~@[`"
+)]
+pub trait OptionArg {
+    type Item;
+
+    fn as_option(&self) -> Option<&Self::Item>;
+}
+
+impl<T> OptionArg for Option<T> {
+    type Item = T;
+
+    fn as_option(&self) -> Option<&T> {
+        self.as_ref()
+    }
+}
+
+impl<O: OptionArg + ?Sized> OptionArg for &O {
+    type Item = O::Item;
+
+    fn as_option(&self) -> Option<&O::Item> {
+        (**self).as_option()
+    }
+}
+
+#[doc(hidden)]
+pub fn option_arg<O: OptionArg + ?Sized>(option: &O) -> Option<&O::Item> {
+    option.as_option()
+}
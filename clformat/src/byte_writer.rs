@@ -0,0 +1,98 @@
+//! Adapts byte buffers so the directives can write to them with `core::fmt::Write`, for when
+//! `std::io::Write` isn't available.
+use core::fmt;
+
+/// Wraps a byte buffer such as `Vec<u8>`, writing the UTF-8 bytes of the output to it.
+///
+/// Only `Extend<u8>` is needed, so it can be used in `no_std` crates with `alloc`, or with
+/// fixed capacity buffers that implement it.
+#[derive(Debug)]
+pub struct ByteWriter<'a, W: ?Sized> {
+    inner: &'a mut W,
+}
+
+impl<'a, W: Extend<u8> + ?Sized> ByteWriter<'a, W> {
+    pub fn new(inner: &'a mut W) -> Self {
+        Self { inner }
+    }
+}
+
+impl<W: Extend<u8> + ?Sized> fmt::Write for ByteWriter<'_, W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.inner.extend(s.bytes());
+        Ok(())
+    }
+}
+
+/// The macro calls `(&mut writer).clformat_writer()`. Method resolution picks
+/// [`FmtWriterKind`] if the writer implements `core::fmt::Write`, otherwise [`BytesKind`]
+/// wraps a byte buffer in a [`ByteWriter`]. Failing both, it takes another reference and
+/// finds [`OtherKind`], which can't be called but explains what the destination can be.
+#[doc(hidden)]
+pub trait FmtWriterKind {
+    fn clformat_writer(self) -> Self;
+}
+
+impl<W: fmt::Write + ?Sized> FmtWriterKind for &mut W {
+    fn clformat_writer(self) -> Self {
+        self
+    }
+}
+
+#[doc(hidden)]
+pub trait BytesKind<'a, W: ?Sized> {
+    fn clformat_writer(self) -> ByteWriter<'a, W>;
+}
+
+impl<'a, W: Extend<u8> + ?Sized> BytesKind<'a, W> for &'a mut W {
+    fn clformat_writer(self) -> ByteWriter<'a, W> {
+        ByteWriter::new(self)
+    }
+}
+
+#[doc(hidden)]
+pub trait OtherKind {
+    fn clformat_writer(self) -> &'static mut dyn fmt::Write
+    where
+        Self: Writer;
+}
+
+impl<W: ?Sized> OtherKind for &mut &mut W {
+    fn clformat_writer(self) -> &'static mut dyn fmt::Write
+    where
+        Self: Writer,
+    {
+        Writer::clformat_writer(self)
+    }
+}
+
+/// Implemented by nothing, so that using a destination that is neither a `fmt::Write` nor a
+/// byte buffer reports this message rather than every trait method resolution tried.
+#[doc(hidden)]
+#[diagnostic::on_unimplemented(
+    message = "the destination of `clformat!` must be a `core::fmt::Write` or a byte buffer",
+    label = "this is neither a `core::fmt::Write` nor `Extend<u8>`",
+    note = "use `t` to write to stdout, or `io!(writer)` to write to a `std::io::Write`"
+)]
+pub trait Writer {
+    fn clformat_writer(self) -> &'static mut dyn fmt::Write;
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::vec::Vec;
+
+    use super::*;
+    use core::fmt::Write;
+
+    #[test]
+    fn writes_bytes() {
+        let mut bytes = Vec::new();
+        let mut writer = ByteWriter::new(&mut bytes);
+        write!(writer, "zork {}", 42).unwrap();
+        writer.write_str(" crème").unwrap();
+        assert_eq!("zork 42 crème".as_bytes(), bytes);
+    }
+}
@@ -0,0 +1,82 @@
+//! Decides which choice of `~[` an argument selects.
+
+/// The index of the choice of `~[a~;b~;c~]` that an argument selects, counting from zero.
+///
+/// This is implemented for the integers, where negative numbers select the `~:;` default.
+/// Implement it for your own types, such as an enum of status codes, to pick their text with
+/// `~[` directly:
+///
+/// ```
+/// use clformat::{clformat, Choice};
+///
+/// enum Status {
+///     Running,
+///     Stopped,
+/// }
+///
+/// impl Choice for Status {
+///     fn choice(&self) -> usize {
+///         match self {
+///             Status::Running => 0,
+///             Status::Stopped => 1,
+///         }
+///     }
+/// }
+///
+/// assert_eq!("stopped", clformat!(nil, "~[running~;stopped~]", Status::Stopped));
+/// ```
+#[diagnostic::on_unimplemented(
+    message = "`~[` requires an argument that implements `clformat::Choice`, such as an integer",
+    label = "this argument selects the choice of `~[`"
+)]
+pub trait Choice {
+    fn choice(&self) -> usize;
+}
+
+/// Values behind references and smart pointers select the same choice as the value itself.
+macro_rules! impl_pointer {
+    ($($t:ty),*) => {
+        $(
+            impl<T: Choice + ?Sized> Choice for $t {
+                fn choice(&self) -> usize {
+                    (**self).choice()
+                }
+            }
+        )*
+    };
+}
+
+impl_pointer!(&T);
+#[cfg(feature = "alloc")]
+impl_pointer!(alloc::boxed::Box<T>, alloc::rc::Rc<T>, alloc::sync::Arc<T>);
+
+/// Integers that don't fit in a `usize`, such as negative numbers, select no choice.
+macro_rules! impl_integer {
+    ($($t:ty),*) => {
+        $(
+            impl Choice for $t {
+                fn choice(&self) -> usize {
+                    usize::try_from(*self).unwrap_or(usize::MAX)
+                }
+            }
+        )*
+    };
+}
+
+impl_integer!(isize, i8, i16, i32, i64, i128, usize, u8, u16, u32, u64, u128);
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+
+    #[test]
+    fn chooses_integers() {
+        assert_eq!(2, 2u8.choice());
+        assert_eq!(0, (&&0i64).choice());
+        assert_eq!(usize::MAX, (-1).choice());
+        assert_eq!(usize::MAX, u128::MAX.choice());
+        assert_eq!(1, std::boxed::Box::new(1).choice());
+    }
+}
@@ -0,0 +1,252 @@
+//! Displays arguments to `~:A` the way Lisp would print them.
+use core::fmt::{self, Display};
+
+/// Formatting used by `~:A`.
+///
+/// `None` is printed as `()`, the Lisp empty list, and `Some(x)` is printed as `x`.
+/// Slices, arrays and `Vec`s of [`ClListItem`]s are printed as Lisp lists, so
+/// `vec![1, 2, 3]` is printed as `(1 2 3)`.
+/// Types that don't implement this trait fall back on their `Display` implementation,
+/// so it only needs implementing for types that should print differently to `~A`.
+pub trait ClDisplay {
+    fn fmt_cl(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result;
+
+    /// Formatting used by `~:A` with a separator, which joins the elements of a list with the
+    /// separator and a space, so `vec![1, 2, 3]` is printed as `1, 2, 3` with `,`. Values
+    /// that aren't lists print as they do with [`ClDisplay::fmt_cl`].
+    fn fmt_joined(&self, separator: char, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let _ = separator;
+        self.fmt_cl(f)
+    }
+}
+
+impl<T: Display> ClDisplay for Option<T> {
+    fn fmt_cl(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Some(value) => value.fmt(f),
+            None => f.write_str("()"),
+        }
+    }
+}
+
+impl<T: ClListItem> ClDisplay for [T] {
+    fn fmt_cl(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("(")?;
+        for (idx, item) in self.iter().enumerate() {
+            if idx > 0 {
+                f.write_str(" ")?;
+            }
+            item.fmt_item(f)?;
+        }
+        f.write_str(")")
+    }
+
+    fn fmt_joined(&self, separator: char, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (idx, item) in self.iter().enumerate() {
+            if idx > 0 {
+                write!(f, "{separator} ")?;
+            }
+            item.fmt_item(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T: ClListItem, const N: usize> ClDisplay for [T; N] {
+    fn fmt_cl(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self[..].fmt_cl(f)
+    }
+
+    fn fmt_joined(&self, separator: char, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self[..].fmt_joined(separator, f)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T: ClListItem> ClDisplay for alloc::vec::Vec<T> {
+    fn fmt_cl(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self[..].fmt_cl(f)
+    }
+
+    fn fmt_joined(&self, separator: char, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self[..].fmt_joined(separator, f)
+    }
+}
+
+impl<T: ClDisplay + ?Sized> ClDisplay for &T {
+    fn fmt_cl(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (**self).fmt_cl(f)
+    }
+
+    fn fmt_joined(&self, separator: char, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (**self).fmt_joined(separator, f)
+    }
+}
+
+/// An element of a list printed by `~:A`.
+///
+/// Rust has no specialization, so the elements can't fall back on `Display` as the arguments
+/// to `~:A` do. Instead this is implemented for the primitive types, strings, options and
+/// lists. Implement it for your own types to print them in lists, usually by calling
+/// `Display::fmt`.
+pub trait ClListItem {
+    fn fmt_item(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result;
+}
+
+impl<T: ClListItem> ClListItem for Option<T> {
+    fn fmt_item(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Some(value) => value.fmt_item(f),
+            None => f.write_str("()"),
+        }
+    }
+}
+
+impl<T: ClListItem> ClListItem for [T] {
+    fn fmt_item(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_cl(f)
+    }
+}
+
+impl<T: ClListItem, const N: usize> ClListItem for [T; N] {
+    fn fmt_item(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_cl(f)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T: ClListItem> ClListItem for alloc::vec::Vec<T> {
+    fn fmt_item(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_cl(f)
+    }
+}
+
+impl<T: ClListItem + ?Sized> ClListItem for &T {
+    fn fmt_item(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (**self).fmt_item(f)
+    }
+}
+
+macro_rules! impl_list_item {
+    ($($t:ty),*) => {
+        $(
+            impl ClListItem for $t {
+                fn fmt_item(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                    Display::fmt(self, f)
+                }
+            }
+        )*
+    };
+}
+
+#[cfg(feature = "alloc")]
+impl_list_item!(alloc::string::String);
+impl_list_item!(
+    str, char, bool, f32, f64, isize, i8, i16, i32, i64, i128, usize, u8, u16, u32, u64, u128
+);
+
+/// Displays a value with its [`ClDisplay`] implementation.
+pub struct Cl<'a, T: ?Sized>(&'a T);
+
+impl<T: ClDisplay + ?Sized> Display for Cl<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt_cl(f)
+    }
+}
+
+/// Displays a value with [`ClDisplay::fmt_joined`], for `~:A` with a separator.
+pub struct Joined<'a, T: ?Sized>(&'a T, char);
+
+impl<T: ClDisplay + ?Sized> Display for Joined<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt_joined(self.1, f)
+    }
+}
+
+/// The macro calls `(&arg).cl_display()`. Method resolution picks [`ClDisplayKind`] if the
+/// argument implements [`ClDisplay`], otherwise it needs another reference and falls back
+/// on [`DisplayKind`].
+#[doc(hidden)]
+pub trait ClDisplayKind {
+    fn cl_display(&self) -> Cl<'_, Self>;
+
+    fn cl_joined(&self, separator: char) -> Joined<'_, Self>;
+}
+
+impl<T: ClDisplay + ?Sized> ClDisplayKind for T {
+    fn cl_display(&self) -> Cl<'_, Self> {
+        Cl(self)
+    }
+
+    fn cl_joined(&self, separator: char) -> Joined<'_, Self> {
+        Joined(self, separator)
+    }
+}
+
+#[doc(hidden)]
+pub trait DisplayKind {
+    type Target: Display + ?Sized;
+
+    fn cl_display(&self) -> &Self::Target;
+
+    fn cl_joined(&self, separator: char) -> &Self::Target;
+}
+
+impl<T: Display + ?Sized> DisplayKind for &T {
+    type Target = T;
+
+    fn cl_display(&self) -> &T {
+        self
+    }
+
+    fn cl_joined(&self, _separator: char) -> &T {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::string::{String, ToString};
+    use std::vec;
+
+    use super::*;
+
+    #[test]
+    fn displays_options() {
+        assert_eq!("()", None::<i32>.cl_display().to_string());
+        assert_eq!("42", Some(42).cl_display().to_string());
+        assert_eq!("()", (&&None::<i32>).cl_display().to_string());
+    }
+
+    #[test]
+    fn falls_back_on_display() {
+        assert_eq!("zork", (&"zork").cl_display().to_string());
+        assert_eq!("42", (&42).cl_display().to_string());
+        assert_eq!("zork", (&String::from("zork")).cl_display().to_string());
+    }
+
+    #[test]
+    fn displays_lists() {
+        assert_eq!("(1 2 3)", [1, 2, 3].cl_display().to_string());
+        assert_eq!("()", (&[] as &[i32]).cl_display().to_string());
+        assert_eq!(
+            "((a b) ())",
+            [vec!["a", "b"], vec![]].cl_display().to_string()
+        );
+        assert_eq!("(1 ())", [Some(1), None].cl_display().to_string());
+    }
+
+    #[test]
+    fn joins_lists() {
+        assert_eq!("", (&[] as &[i32]).cl_joined(',').to_string());
+        assert_eq!("1", [1].cl_joined(',').to_string());
+        assert_eq!("1, 2, 3", vec![1, 2, 3].cl_joined(',').to_string());
+        assert_eq!(
+            "(a b); ()",
+            [vec!["a", "b"], vec![]].cl_joined(';').to_string()
+        );
+        assert_eq!("zork", (&"zork").cl_joined(',').to_string());
+    }
+}
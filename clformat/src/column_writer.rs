@@ -0,0 +1,61 @@
+//! Keeps track of the column the output has reached, for directives that depend on it.
+use core::fmt;
+
+use crate::Ruler;
+
+/// Wraps a `fmt::Write`, measuring the columns written since the last newline.
+#[derive(Debug)]
+pub struct ColumnWriter<W> {
+    inner: W,
+    line: Ruler,
+}
+
+impl<W: fmt::Write> ColumnWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            line: Ruler::default(),
+        }
+    }
+
+    /// The column the next character will be written to, counted from zero.
+    pub fn column(&self) -> usize {
+        self.line.length()
+    }
+}
+
+impl<W: fmt::Write> fmt::Write for ColumnWriter<W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.inner.write_str(s)?;
+        match s.rfind('\n') {
+            Some(idx) => {
+                self.line = Ruler::default();
+                self.line.write_str(&s[idx + 1..])
+            }
+            None => self.line.write_str(s),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::string::String;
+
+    use super::*;
+    use core::fmt::Write;
+
+    #[test]
+    fn counts_columns_since_newline() {
+        let mut output = String::new();
+        let mut writer = ColumnWriter::new(&mut output);
+        write!(writer, "zork").unwrap();
+        assert_eq!(4, writer.column());
+        write!(writer, " nork\nook").unwrap();
+        assert_eq!(3, writer.column());
+        writeln!(writer).unwrap();
+        assert_eq!(0, writer.column());
+        assert_eq!("zork nork\nook\n", output);
+    }
+}
@@ -1,34 +1,55 @@
-//! Decimal helper struct to format decimals.
+//! Decimal helper struct to format decimals, and integers in other bases.
+use crate::locale::numeral;
 use crate::num::Num;
 
 #[derive(Clone, Debug, Default)]
-pub struct Decimal<T> {
-    number: T,
+pub struct Decimal {
+    magnitude: u128,
+    negative: bool,
     pad_char: char,
     comma_char: char,
     comma_interval: usize,
-    divisor: usize,
+    /// The interval between commas after the first group.
+    secondary_interval: usize,
+    min_columns: usize,
+    radix: u32,
+    /// Digits above 9 are lowercase rather than uppercase.
+    lowercase: bool,
+    /// The digit zero of the numeral system the digits below 10 are printed in.
+    zero: char,
+    /// The part of the `0x` style prefix still to be written.
+    prefix: &'static str,
+    divisor: u128,
     digits: usize,
+    /// A magnitude too big for a `u128`, with its digits in the current radix, most
+    /// significant first.
+    #[cfg(feature = "bigint")]
+    big: Option<(num_bigint::BigUint, alloc::vec::Vec<u8>)>,
+    /// The text printed in place of the digits for a float that isn't finite.
+    text: &'static str,
     print_commas: bool,
     printed_comma: bool,
     print_sign: bool,
     printed_sign: bool,
+    /// The padding goes between the sign and the digits rather than before the sign.
+    pad_after_sign: bool,
     pad: usize,
 }
 
-fn divisor<T: Num>(number: T) -> (usize, usize) {
+fn divisor(magnitude: u128, radix: u32) -> (u128, usize) {
+    let radix = u128::from(radix);
     let mut divisor = 1;
     let mut count = 1;
-    while number.divide_by(divisor).abs() >= 10 {
-        divisor *= 10;
+    while magnitude / divisor >= radix {
+        divisor *= radix;
         count += 1;
     }
 
-    (divisor as usize, count)
+    (divisor, count)
 }
 
-impl<T: Num> Decimal<T> {
-    pub fn new(
+impl Decimal {
+    pub fn new<T: Num>(
         min_columns: usize,
         pad_char: char,
         comma_char: char,
@@ -37,70 +58,201 @@ impl<T: Num> Decimal<T> {
         print_sign: bool,
         number: T,
     ) -> Self {
-        let (divisor, digits) = divisor(number);
+        let magnitude = number.magnitude();
+        let negative = number.is_negative();
+        let (divisor, digits) = divisor(magnitude, 10);
 
-        // Take the sign and any commas into consideration when calculating -
-        // the number of columns for padding.
-        let columns = if number < T::zero() || print_sign {
-            digits + 1
-        } else {
-            digits
-        } + if print_commas {
-            (digits - 1) / comma_interval
-        } else {
-            0
-        };
-
-        let pad = if min_columns > digits {
-            min_columns - columns
-        } else {
-            0
-        };
-
-        Self {
+        let mut decimal = Self {
             pad_char,
             comma_char,
             comma_interval,
+            secondary_interval: comma_interval,
+            min_columns,
+            radix: 10,
+            lowercase: false,
+            zero: '0',
+            prefix: "",
             print_commas,
             // Set to true so we don't output a comma at the first char
             printed_comma: true,
             print_sign,
             printed_sign: false,
-            number,
+            pad_after_sign: false,
+            magnitude,
+            negative,
             divisor,
             digits,
-            pad,
+            #[cfg(feature = "bigint")]
+            big: None,
+            text: "",
+            pad: 0,
+        };
+        if let Some(text) = number.non_finite() {
+            decimal.text = text;
+            decimal.digits = text.len();
+            decimal.print_commas = false;
+        }
+        #[cfg(feature = "bigint")]
+        if let Some(big) = number
+            .big_magnitude()
+            .filter(|big| u128::try_from(big).is_err())
+        {
+            decimal.big = Some((big, alloc::vec::Vec::new()));
+            decimal = decimal.radix(10);
+        }
+        decimal.pad = decimal.padding();
+        decimal
+    }
+
+    /// Groups the digits after the first group `interval` at a time, so that an interval
+    /// of 2 gives the Indian style `12,34,567`.
+    pub fn secondary_interval(mut self, interval: usize) -> Self {
+        self.secondary_interval = interval;
+        self.pad = self.padding();
+        self
+    }
+
+    /// Prints the number in base `radix`, between 2 and 36. As in Lisp, digits above 9 are
+    /// uppercase.
+    pub fn radix(mut self, radix: u32) -> Self {
+        (self.divisor, self.digits) = divisor(self.magnitude, radix);
+        #[cfg(feature = "bigint")]
+        if let Some((big, digits)) = &mut self.big {
+            *digits = big.to_radix_be(radix);
+            self.digits = digits.len();
+        }
+        if !self.text.is_empty() {
+            self.digits = self.text.len();
+        }
+        self.radix = radix;
+        self.pad = self.padding();
+        self
+    }
+
+    /// Whether the digits come from a big integer rather than the `u128` magnitude.
+    #[cfg(feature = "fast-num")]
+    fn is_big(&self) -> bool {
+        #[cfg(feature = "bigint")]
+        return self.big.is_some();
+        #[cfg(not(feature = "bigint"))]
+        false
+    }
+
+    /// Prefixes the digits with `0x`, `0o` or `0b` and prints them in lowercase, as Rust's
+    /// `{:#x}` does. Other bases, and floats that aren't finite, have no prefix.
+    pub fn rust_prefix(mut self) -> Self {
+        self.prefix = match self.radix {
+            _ if !self.text.is_empty() => "",
+            16 => "0x",
+            8 => "0o",
+            2 => "0b",
+            _ => "",
+        };
+        self.lowercase = true;
+        self.pad = self.padding();
+        self
+    }
+
+    /// Prints digits above 9 in lowercase.
+    pub fn lowercase(mut self) -> Self {
+        self.lowercase = true;
+        self
+    }
+
+    /// Prints the digits counting up from `zero` rather than `0`, such as `'٠'` for
+    /// Arabic-Indic digits. Digits above 9 in other bases are still letters.
+    pub fn zero_digit(mut self, zero: char) -> Self {
+        self.zero = zero;
+        self
+    }
+
+    /// Pads between the sign and the digits, so that `-42` padded to 6 columns is `-   42`.
+    /// Zeros are always padded after the sign.
+    pub fn pad_after_sign(mut self) -> Self {
+        self.pad_after_sign = true;
+        self
+    }
+
+    /// The number of pad characters needed, taking the sign, prefix and any commas into
+    /// consideration.
+    fn padding(&self) -> usize {
+        let sign = usize::from(self.negative || self.print_sign) + self.prefix.len();
+        // An interval of 0 puts no more commas after the first group, as in `next`.
+        let commas = match self.secondary_interval {
+            _ if !self.print_commas || self.digits <= self.comma_interval => 0,
+            0 => usize::from(self.comma_interval > 0),
+            interval => {
+                (self.digits - self.comma_interval - 1) / interval
+                    + usize::from(self.comma_interval > 0)
+            }
+        };
+
+        self.min_columns.saturating_sub(self.digits + sign + commas)
+    }
+}
+
+impl Decimal {
+    /// The sign the first time it is asked for, then the characters of the prefix.
+    fn sign(&mut self) -> Option<char> {
+        if !self.printed_sign {
+            self.printed_sign = true;
+            if self.negative {
+                return Some('-');
+            } else if self.print_sign {
+                return Some('+');
+            }
+        }
+
+        let c = self.prefix.chars().next()?;
+        self.prefix = &self.prefix[c.len_utf8()..];
+        Some(c)
+    }
+}
+
+impl Decimal {
+    /// The most significant digit not yet printed.
+    fn next_digit(&mut self) -> u128 {
+        #[cfg(feature = "bigint")]
+        if let Some((_, digits)) = &self.big {
+            let digit = digits[digits.len() - self.digits];
+            self.digits -= 1;
+            return u128::from(digit);
         }
+
+        let digit = self.magnitude / self.divisor % u128::from(self.radix);
+        self.divisor /= u128::from(self.radix);
+        self.digits -= 1;
+        digit
     }
 }
 
-impl<T: Num> core::iter::Iterator for Decimal<T> {
+impl core::iter::Iterator for Decimal {
     type Item = char;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.divisor == 0 {
+        if self.digits == 0 {
             return None;
         }
 
+        // Zeros go after the sign and prefix so they read as part of the number.
+        if self.pad_char == '0' || self.pad_after_sign {
+            if let Some(sign) = self.sign() {
+                return Some(sign);
+            }
+        }
+
         if self.pad > 0 {
             self.pad -= 1;
             return Some(self.pad_char);
         }
 
-        if !self.printed_sign {
-            self.printed_sign = true;
-            if self.number > T::zero() {
-                if self.print_sign {
-                    return Some('+');
-                }
-            } else {
-                return Some('-');
-            }
+        if let Some(sign) = self.sign() {
+            return Some(sign);
         }
 
         if self.print_commas
-            && self.digits % self.comma_interval == 0
-            && self.divisor != 1
+            && self.digits >= self.comma_interval
+            && (self.digits - self.comma_interval).is_multiple_of(self.secondary_interval)
             && !self.printed_comma
         {
             self.printed_comma = true;
@@ -108,11 +260,72 @@ impl<T: Num> core::iter::Iterator for Decimal<T> {
         }
 
         self.printed_comma = false;
-        let digit = self.number.divide_by(self.divisor as isize) % 10;
-        self.divisor /= 10;
-        self.digits -= 1;
+        if let Some(c) = self.text.chars().next() {
+            self.text = &self.text[c.len_utf8()..];
+            self.digits -= 1;
+            return Some(c);
+        }
+
+        let digit = self.next_digit();
+
+        if digit < 10 && self.zero != '0' {
+            return Some(numeral(self.zero, digit as u32));
+        }
+
+        let digit = core::char::from_digit(digit as u32, self.radix).unwrap();
+        if self.lowercase {
+            Some(digit)
+        } else {
+            Some(digit.to_ascii_uppercase())
+        }
+    }
+}
+
+impl Decimal {
+    /// Writes the characters the iterator would produce to `writer`, collecting them in a
+    /// buffer so that they are written a few at a time rather than one by one.
+    pub fn write_to<W: core::fmt::Write + ?Sized>(&self, writer: &mut W) -> core::fmt::Result {
+        // A number without padding, commas or other digits is its sign then the digits,
+        // which `itoa` writes in one go.
+        #[cfg(feature = "fast-num")]
+        if !self.printed_sign
+            && self.pad == 0
+            && self.radix == 10
+            && self.zero == '0'
+            && self.prefix.is_empty()
+            && !self.is_big()
+            && self.text.is_empty()
+            && !(self.print_commas && self.digits > self.comma_interval)
+        {
+            if self.negative {
+                writer.write_char('-')?;
+            } else if self.print_sign {
+                writer.write_char('+')?;
+            }
+            return writer.write_str(itoa::Buffer::new().format(self.magnitude));
+        }
 
-        Some(core::char::from_digit(digit.unsigned_abs() as u32, 10).unwrap())
+        let mut buffer = [0; 64];
+        let mut len = 0;
+        for c in self.clone() {
+            if len + c.len_utf8() > buffer.len() {
+                writer.write_str(buffered(&buffer[..len]))?;
+                len = 0;
+            }
+            len += c.encode_utf8(&mut buffer[len..]).len();
+        }
+        writer.write_str(buffered(&buffer[..len]))
+    }
+}
+
+/// The characters collected in the buffer of [`Decimal::write_to`].
+fn buffered(buffer: &[u8]) -> &str {
+    core::str::from_utf8(buffer).expect("the buffer holds whole characters")
+}
+
+impl core::fmt::Display for Decimal {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        self.write_to(f)
     }
 }
 
@@ -147,6 +360,198 @@ mod tests {
         assert_eq!("-4,200,000".to_string(), num);
     }
 
+    #[test]
+    fn ignores_the_comma_interval_without_commas() {
+        let decimal = Decimal::new(0, ' ', ',', 2, false, false, 4_200_000);
+        assert_eq!("4200000", decimal.collect::<String>());
+
+        let decimal = Decimal::new(9, ' ', ',', 2, false, false, 4_200_000);
+        assert_eq!("  4200000", decimal.collect::<String>());
+    }
+
+    #[test]
+    fn prints_other_bases() {
+        let decimal = Decimal::new(0, ' ', ',', 3, false, false, 255).radix(16);
+        assert_eq!("FF".to_string(), decimal.collect::<String>());
+
+        let decimal = Decimal::new(0, ' ', ',', 3, false, false, -8).radix(8);
+        assert_eq!("-10".to_string(), decimal.collect::<String>());
+
+        let decimal = Decimal::new(0, ' ', ',', 4, true, false, 255).radix(2);
+        assert_eq!("1111,1111".to_string(), decimal.collect::<String>());
+
+        let decimal = Decimal::new(0, ' ', ',', 3, false, false, u128::MAX).radix(2);
+        assert_eq!(128, decimal.count());
+    }
+
+    #[test]
+    fn prints_lowercase_digits() {
+        let decimal = Decimal::new(0, ' ', ',', 3, false, false, 0xbeef).radix(16);
+        assert_eq!("BEEF", decimal.collect::<String>());
+        let decimal = Decimal::new(0, ' ', ',', 3, false, false, 0xbeef)
+            .radix(16)
+            .lowercase();
+        assert_eq!("beef", decimal.collect::<String>());
+    }
+
+    #[test]
+    fn prints_rust_prefixes() {
+        let decimal = Decimal::new(0, ' ', ',', 3, false, false, 255)
+            .radix(16)
+            .rust_prefix();
+        assert_eq!("0xff".to_string(), decimal.collect::<String>());
+
+        let decimal = Decimal::new(8, ' ', ',', 3, false, false, -255)
+            .radix(16)
+            .rust_prefix();
+        assert_eq!("   -0xff".to_string(), decimal.collect::<String>());
+
+        let decimal = Decimal::new(6, '0', ',', 3, false, false, 255)
+            .radix(16)
+            .rust_prefix();
+        assert_eq!("0x00ff".to_string(), decimal.collect::<String>());
+
+        let decimal = Decimal::new(0, ' ', ',', 3, false, false, 8)
+            .radix(8)
+            .rust_prefix();
+        assert_eq!("0o10".to_string(), decimal.collect::<String>());
+    }
+
+    #[test]
+    fn groups_with_a_secondary_interval() {
+        let decimal = Decimal::new(0, ' ', ',', 3, true, false, 1_234_567).secondary_interval(2);
+        assert_eq!("12,34,567".to_string(), decimal.collect::<String>());
+
+        let decimal = Decimal::new(0, ' ', ',', 3, true, false, 123_456_789).secondary_interval(2);
+        assert_eq!("12,34,56,789".to_string(), decimal.collect::<String>());
+
+        let decimal = Decimal::new(0, ' ', ',', 3, true, false, 567).secondary_interval(2);
+        assert_eq!("567".to_string(), decimal.collect::<String>());
+
+        let decimal = Decimal::new(12, ' ', ',', 3, true, false, -1_234_567).secondary_interval(2);
+        assert_eq!("  -12,34,567".to_string(), decimal.collect::<String>());
+    }
+
+    #[test]
+    fn groups_once_with_a_secondary_interval_of_zero() {
+        let decimal = Decimal::new(10, ' ', ',', 3, true, false, 1_234_567).secondary_interval(0);
+        assert_eq!("  1234,567".to_string(), decimal.collect::<String>());
+
+        let decimal = Decimal::new(5, ' ', ',', 0, true, false, 1_234);
+        assert_eq!(" 1234".to_string(), decimal.collect::<String>());
+    }
+
+    #[test]
+    fn pads_zeros_after_the_sign() {
+        let decimal = Decimal::new(6, '0', ',', 3, false, false, -42);
+        assert_eq!("-00042".to_string(), decimal.collect::<String>());
+
+        let decimal = Decimal::new(6, '0', ',', 3, false, true, 42);
+        assert_eq!("+00042".to_string(), decimal.collect::<String>());
+
+        let decimal = Decimal::new(6, '0', ',', 3, false, false, 42);
+        assert_eq!("000042".to_string(), decimal.collect::<String>());
+
+        let decimal = Decimal::new(6, '*', ',', 3, false, false, -42);
+        assert_eq!("***-42".to_string(), decimal.collect::<String>());
+    }
+
+    #[test]
+    fn displays_as_iterated() {
+        let numbers = [0, 7, -7, 42, -1_234_567, i64::MAX as i128, i128::MIN];
+        for number in numbers {
+            let decimals = [
+                Decimal::new(0, ' ', ',', 3, false, false, number),
+                Decimal::new(0, ' ', ',', 3, false, true, number),
+                Decimal::new(0, ' ', ',', 3, true, false, number),
+                Decimal::new(12, '0', ',', 3, false, false, number),
+                Decimal::new(12, ' ', ',', 3, true, true, number).pad_after_sign(),
+                Decimal::new(0, ' ', ',', 3, false, false, number).radix(16),
+                Decimal::new(0, ' ', ',', 3, false, false, number).zero_digit('٠'),
+            ];
+            for decimal in decimals {
+                let iterated = decimal.clone().collect::<String>();
+                assert_eq!(iterated, decimal.to_string(), "{decimal:?}");
+
+                let mut written = String::new();
+                decimal.write_to(&mut written).unwrap();
+                assert_eq!(iterated, written, "{decimal:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn writes_more_than_the_buffer() {
+        // Wide padding and multibyte digits fill the buffer several times over.
+        let decimal = Decimal::new(100, '·', '٬', 3, true, false, i128::MIN).zero_digit('٠');
+        let mut written = String::new();
+        decimal.write_to(&mut written).unwrap();
+        assert_eq!(decimal.collect::<String>(), written);
+        assert_eq!(100, written.chars().count());
+    }
+
+    #[test]
+    fn prints_other_numerals() {
+        let decimal = Decimal::new(0, ' ', ',', 3, false, false, 123).zero_digit('٠');
+        assert_eq!("١٢٣".to_string(), decimal.collect::<String>());
+
+        let decimal = Decimal::new(0, ' ', '٬', 3, true, false, -1_234_567).zero_digit('٠');
+        assert_eq!("-١٬٢٣٤٬٥٦٧".to_string(), decimal.collect::<String>());
+
+        let decimal = Decimal::new(0, ' ', ',', 3, false, false, 0x1a)
+            .radix(16)
+            .zero_digit('०');
+        assert_eq!("१A".to_string(), decimal.collect::<String>());
+    }
+
+    #[test]
+    fn pads_after_the_sign() {
+        let decimal = Decimal::new(6, ' ', ',', 3, false, false, -42).pad_after_sign();
+        assert_eq!("-   42".to_string(), decimal.collect::<String>());
+
+        let decimal = Decimal::new(6, '*', ',', 3, false, true, 42).pad_after_sign();
+        assert_eq!("+***42".to_string(), decimal.collect::<String>());
+
+        let decimal = Decimal::new(6, ' ', ',', 3, false, false, 42).pad_after_sign();
+        assert_eq!("    42".to_string(), decimal.collect::<String>());
+    }
+
+    #[test]
+    fn rounds_floats() {
+        let decimal = Decimal::new(0, ' ', ',', 3, false, false, 3.7);
+        assert_eq!("4".to_string(), decimal.collect::<String>());
+
+        let decimal = Decimal::new(0, ' ', ',', 3, false, false, -3.2);
+        assert_eq!("-3".to_string(), decimal.collect::<String>());
+
+        let decimal = Decimal::new(0, ' ', ',', 3, false, false, 2.5_f32);
+        assert_eq!("3".to_string(), decimal.collect::<String>());
+
+        let decimal = Decimal::new(0, ' ', ',', 3, false, false, -0.2);
+        assert_eq!("0".to_string(), decimal.collect::<String>());
+
+        let decimal = Decimal::new(0, ' ', ',', 3, false, false, 0.49999999999999994);
+        assert_eq!("0".to_string(), decimal.collect::<String>());
+
+        let decimal = Decimal::new(0, ' ', ',', 3, false, false, -0.5);
+        assert_eq!("-1".to_string(), decimal.collect::<String>());
+
+        let decimal = Decimal::new(0, ' ', ',', 3, false, false, 4503599627370495.5);
+        assert_eq!("4503599627370496".to_string(), decimal.collect::<String>());
+    }
+
+    #[test]
+    fn prints_floats_that_are_not_finite() {
+        let decimal = Decimal::new(0, ' ', ',', 3, false, false, f64::NAN);
+        assert_eq!("NaN".to_string(), decimal.collect::<String>());
+
+        let decimal = Decimal::new(6, '0', ',', 1, true, true, f64::INFINITY);
+        assert_eq!("+00inf".to_string(), decimal.collect::<String>());
+
+        let decimal = Decimal::new(0, ' ', ',', 3, false, false, f32::NEG_INFINITY).radix(16);
+        assert_eq!("-inf".to_string(), decimal.collect::<String>());
+    }
+
     #[test]
     fn prints_alternative_separators() {
         let decimal = Decimal::new(0, ' ', '_', 3, true, false, 4200);
@@ -162,6 +567,23 @@ mod tests {
         assert_eq!("420_0000".to_string(), num);
     }
 
+    #[test]
+    fn groups_from_the_right() {
+        // Commas are placed by counting back from the least significant digit, so
+        // a short leading group goes at the front as in CL.
+        let decimal = Decimal::new(0, ' ', ',', 3, true, false, 1_234_567);
+        let num = decimal.collect::<String>();
+        assert_eq!("1,234,567".to_string(), num);
+
+        let decimal = Decimal::new(0, ' ', ',', 2, true, false, 1_234_567);
+        let num = decimal.collect::<String>();
+        assert_eq!("1,23,45,67".to_string(), num);
+
+        let decimal = Decimal::new(0, ' ', ',', 2, true, false, 123_456);
+        let num = decimal.collect::<String>();
+        assert_eq!("12,34,56".to_string(), num);
+    }
+
     #[test]
     fn pads() {
         let decimal = Decimal::new(2, ' ', ',', 3, true, false, 420);
@@ -185,6 +607,97 @@ mod tests {
         assert_eq!("---4,200".to_string(), num);
     }
 
+    #[test]
+    fn pads_sign_and_commas() {
+        // The sign and commas take up columns, so there is nothing left to pad.
+        let decimal = Decimal::new(5, ' ', ',', 3, true, false, -4200);
+        let num = decimal.collect::<String>();
+        assert_eq!("-4,200".to_string(), num);
+
+        let decimal = Decimal::new(6, ' ', ',', 3, true, false, -4200);
+        let num = decimal.collect::<String>();
+        assert_eq!("-4,200".to_string(), num);
+
+        let decimal = Decimal::new(7, ' ', ',', 3, true, false, -4200);
+        let num = decimal.collect::<String>();
+        assert_eq!(" -4,200".to_string(), num);
+
+        let decimal = Decimal::new(5, ' ', ',', 3, true, true, 4200);
+        let num = decimal.collect::<String>();
+        assert_eq!("+4,200".to_string(), num);
+    }
+
+    #[test]
+    fn large_numbers() {
+        let decimal = Decimal::new(0, ' ', ',', 3, false, false, u64::MAX);
+        let num = decimal.collect::<String>();
+        assert_eq!(u64::MAX.to_string(), num);
+
+        let decimal = Decimal::new(0, ' ', ',', 3, false, false, i128::MIN);
+        let num = decimal.collect::<String>();
+        assert_eq!(i128::MIN.to_string(), num);
+
+        let decimal = Decimal::new(0, ' ', ',', 3, false, false, u128::MAX);
+        let num = decimal.collect::<String>();
+        assert_eq!(u128::MAX.to_string(), num);
+
+        let decimal = Decimal::new(0, ' ', ',', 3, true, false, u128::MAX);
+        let num = decimal.collect::<String>();
+        assert_eq!(
+            "340,282,366,920,938,463,463,374,607,431,768,211,455".to_string(),
+            num
+        );
+    }
+
+    #[cfg(feature = "bigint")]
+    #[test]
+    fn big_numbers() {
+        use num_bigint::{BigInt, BigUint};
+
+        let big = "1234567890123456789012345678901234567890"
+            .parse::<BigInt>()
+            .unwrap();
+        let decimal = Decimal::new(0, ' ', ',', 3, true, false, &big);
+        assert_eq!(
+            "1,234,567,890,123,456,789,012,345,678,901,234,567,890".to_string(),
+            decimal.collect::<String>()
+        );
+
+        let decimal = Decimal::new(0, ' ', ',', 3, false, false, -&big);
+        assert_eq!(
+            "-1234567890123456789012345678901234567890".to_string(),
+            decimal.to_string()
+        );
+
+        let decimal = Decimal::new(44, '0', ',', 3, false, true, &big);
+        assert_eq!(
+            "+0001234567890123456789012345678901234567890".to_string(),
+            decimal.collect::<String>()
+        );
+
+        let big = BigUint::from(u128::MAX) + 1u8;
+        let decimal = Decimal::new(0, ' ', ',', 3, false, false, &big).radix(16);
+        assert_eq!(
+            "100000000000000000000000000000000",
+            decimal.collect::<String>()
+        );
+
+        // Numbers that fit in a `u128` are printed as usual.
+        let decimal = Decimal::new(0, ' ', ',', 3, true, false, BigInt::from(-4200));
+        assert_eq!("-4,200".to_string(), decimal.collect::<String>());
+    }
+
+    #[test]
+    fn zero() {
+        let decimal = Decimal::new(0, ' ', ',', 3, true, false, 0);
+        let num = decimal.collect::<String>();
+        assert_eq!("0".to_string(), num);
+
+        let decimal = Decimal::new(0, ' ', ',', 3, true, true, 0);
+        let num = decimal.collect::<String>();
+        assert_eq!("+0".to_string(), num);
+    }
+
     #[test]
     fn sign() {
         let decimal = Decimal::new(2, ' ', ',', 3, true, true, 420);
@@ -0,0 +1,55 @@
+//! Durations printed as a clock, as `Duration` only implements `Debug`.
+use core::fmt;
+use core::time::Duration;
+
+/// Prints a `Duration` as `HH:MM:SS.mmm` with `~A`, so `Elapsed(Duration::from_millis(1500))`
+/// prints `00:00:01.500`.
+///
+/// Hours run past 99 rather than wrapping into days, and the milliseconds are truncated.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Elapsed(pub Duration);
+
+impl fmt::Display for Elapsed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let seconds = self.0.as_secs();
+        write!(
+            f,
+            "{:02}:{:02}:{:02}.{:03}",
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60,
+            self.0.subsec_millis()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::string::ToString;
+
+    #[test]
+    fn prints_zero() {
+        assert_eq!("00:00:00.000", Elapsed(Duration::ZERO).to_string());
+    }
+
+    #[test]
+    fn prints_fractions_of_a_second() {
+        assert_eq!(
+            "00:00:00.250",
+            Elapsed(Duration::from_micros(250_999)).to_string()
+        );
+    }
+
+    #[test]
+    fn prints_hours() {
+        let duration = Duration::from_secs(3 * 3600 + 25 * 60 + 7) + Duration::from_millis(42);
+        assert_eq!("03:25:07.042", Elapsed(duration).to_string());
+        assert_eq!(
+            "100:00:00.000",
+            Elapsed(Duration::from_secs(100 * 3600)).to_string()
+        );
+    }
+}
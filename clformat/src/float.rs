@@ -0,0 +1,540 @@
+//! Float helper struct to format floats as `~F` does.
+use core::fmt::{self, Display, Write};
+
+use crate::locale::numeral;
+
+/// Displays a float the way `~F` does, right aligned in a field of `width` columns padded
+/// with `pad_char`, or left aligned with [`Float::left_justify`].
+///
+/// Without a number of decimal places, the value is displayed with at least one digit after
+/// the decimal point, so that `100.0` prints as `100.0` rather than `100`. Otherwise as many
+/// digits are printed as are needed to represent the value.
+///
+/// Negative zero, and negative numbers that round to zero such as `-0.001` with 2 decimal
+/// places, are printed without a sign unless `print_sign` is set.
+#[derive(Clone, Copy, Debug)]
+pub struct Float<T> {
+    width: usize,
+    num_decimal_places: Option<usize>,
+    pad_char: char,
+    print_sign: bool,
+    decimal_char: char,
+    zero: char,
+    leading_zero: bool,
+    round_half_up: bool,
+    left_justify: bool,
+    value: T,
+}
+
+impl<T> Float<T> {
+    pub fn new(
+        width: usize,
+        num_decimal_places: Option<usize>,
+        pad_char: char,
+        print_sign: bool,
+        value: T,
+    ) -> Self {
+        Self {
+            width,
+            num_decimal_places,
+            pad_char,
+            print_sign,
+            decimal_char: '.',
+            zero: '0',
+            leading_zero: true,
+            round_half_up: false,
+            left_justify: false,
+            value,
+        }
+    }
+
+    /// Prints numbers between -1 and 1 without the zero before the decimal point, as `.5`
+    /// rather than `0.5`.
+    pub fn drop_leading_zero(mut self) -> Self {
+        self.leading_zero = false;
+        self
+    }
+
+    /// Rounds a half in the last decimal place away from zero, going by the shortest decimal
+    /// representation of the value. `2.675` is stored as slightly less, so it rounds to `2.67`
+    /// by default but `2.68` with this set.
+    pub fn round_half_up(mut self) -> Self {
+        self.round_half_up = true;
+        self
+    }
+
+    /// Pads after the number rather than before it, as `~#F` does.
+    pub fn left_justify(mut self) -> Self {
+        self.left_justify = true;
+        self
+    }
+
+    /// Prints `decimal_char` for the decimal point rather than `.`.
+    pub fn decimal_char(mut self, decimal_char: char) -> Self {
+        self.decimal_char = decimal_char;
+        self
+    }
+
+    /// Prints the digits counting up from `zero` rather than `0`, such as `'٠'` for
+    /// Arabic-Indic digits.
+    pub fn zero_digit(mut self, zero: char) -> Self {
+        self.zero = zero;
+        self
+    }
+}
+
+impl<T: Display> Display for Float<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value = Precise {
+            value: &self.value,
+            precision: self.num_decimal_places,
+            sign_plus: self.print_sign,
+            half_up: self.round_half_up,
+        };
+        let mut inspect = Inspect::default();
+        write!(inspect, "{value}")?;
+
+        // Anything other than digits and a sign, such as `.`, `e` or `inf`, is left alone.
+        let point = if inspect.whole && value.precision.is_none() {
+            ".0"
+        } else {
+            ""
+        };
+        let skip_sign = inspect.negative_zero() && !value.sign_plus;
+        let skip_zero = inspect.leading_zero() && !self.leading_zero;
+        let length = inspect.length + point.len() - usize::from(skip_sign) - usize::from(skip_zero);
+
+        if !self.left_justify {
+            self.pad(length, f)?;
+        }
+        write!(
+            Adjust {
+                inner: &mut *f,
+                skip_sign,
+                skip_zero,
+                decimal_char: self.decimal_char,
+                zero: self.zero,
+            },
+            "{value}{point}"
+        )?;
+        if self.left_justify {
+            self.pad(length, f)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T> Float<T> {
+    /// Fills the columns of the field that the `length` characters of the number don't.
+    fn pad(&self, length: usize, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for _ in length..self.width {
+            f.write_char(self.pad_char)?;
+        }
+        Ok(())
+    }
+}
+
+/// Displays the value of a [`Float`] with its decimal places and sign, but without padding.
+struct Precise<'a, T> {
+    value: &'a T,
+    precision: Option<usize>,
+    sign_plus: bool,
+    half_up: bool,
+}
+
+impl<T: Display> Display for Precise<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let (Some(precision), true) = (self.precision, self.half_up) {
+            // The shortest representation is scanned for where to round, then rounded as it
+            // is written again. Anything other than plain digits, such as `inf`, is left to
+            // the standard rounding.
+            let shortest = Precise {
+                precision: None,
+                half_up: false,
+                ..*self
+            };
+            let mut scan = Scan::new(precision);
+            write!(scan, "{shortest}")?;
+            if !scan.unusual {
+                let mut round = Round {
+                    inner: &mut *f,
+                    scan: &scan,
+                    point: false,
+                    digits: 0,
+                    decimals: 0,
+                };
+                write!(round, "{shortest}")?;
+                return round.finish();
+            }
+        }
+
+        match (self.precision, self.sign_plus) {
+            (Some(precision), true) => write!(f, "{:+.precision$}", self.value),
+            (Some(precision), false) => write!(f, "{:.precision$}", self.value),
+            (None, true) => write!(f, "{:+}", self.value),
+            (None, false) => write!(f, "{}", self.value),
+        }
+    }
+}
+
+/// Finds how the shortest representation of a value changes when it is rounded half up to
+/// `precision` decimal places.
+struct Scan {
+    precision: usize,
+    /// Something other than a sign, digits and a decimal point was written.
+    unusual: bool,
+    point: bool,
+    decimals: usize,
+    /// The number of digits kept, those before the point and `precision` after it.
+    kept: usize,
+    /// The index of the last kept digit that isn't a 9, where carrying one stops.
+    last_non_nine: Option<usize>,
+    round_up: bool,
+}
+
+impl Scan {
+    fn new(precision: usize) -> Self {
+        Self {
+            precision,
+            unusual: false,
+            point: false,
+            decimals: 0,
+            kept: 0,
+            last_non_nine: None,
+            round_up: false,
+        }
+    }
+}
+
+impl Write for Scan {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            match c {
+                '-' | '+' if self.kept == 0 && !self.point => (),
+                '.' if !self.point => self.point = true,
+                '0'..='9' => {
+                    if self.point {
+                        self.decimals += 1;
+                    }
+                    if !self.point || self.decimals <= self.precision {
+                        if c != '9' {
+                            self.last_non_nine = Some(self.kept);
+                        }
+                        self.kept += 1;
+                    } else if self.decimals == self.precision + 1 {
+                        self.round_up = c >= '5';
+                    }
+                }
+                _ => self.unusual = true,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Writes the shortest representation of a value to `inner`, rounded as [`Scan`] found.
+struct Round<'a, W> {
+    inner: W,
+    scan: &'a Scan,
+    point: bool,
+    /// The number of kept digits written.
+    digits: usize,
+    decimals: usize,
+}
+
+impl<W: Write> Round<'_, W> {
+    /// Pads the decimal places with zeros.
+    fn finish(mut self) -> fmt::Result {
+        let precision = self.scan.precision;
+        if precision > 0 && !self.point {
+            self.inner.write_char('.')?;
+        }
+        for _ in self.decimals.min(precision)..precision {
+            self.inner.write_char('0')?;
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for Round<'_, W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let scan = self.scan;
+        for c in s.chars() {
+            match c {
+                '.' => {
+                    self.point = true;
+                    if scan.precision > 0 {
+                        self.inner.write_char('.')?;
+                    }
+                }
+                '0'..='9' => {
+                    if self.point {
+                        self.decimals += 1;
+                        if self.decimals > scan.precision {
+                            continue;
+                        }
+                    }
+                    // Rounding 9.99 up carries into a new leading digit.
+                    if self.digits == 0 && scan.round_up && scan.last_non_nine.is_none() {
+                        self.inner.write_char('1')?;
+                    }
+                    let digit = match scan.last_non_nine {
+                        _ if !scan.round_up => c,
+                        Some(last) if self.digits < last => c,
+                        Some(last) if self.digits == last => (c as u8 + 1) as char,
+                        _ => '0',
+                    };
+                    self.inner.write_char(digit)?;
+                    self.digits += 1;
+                }
+                c => self.inner.write_char(c)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Measures the displayed value and checks whether it is a whole number, negative zero or
+/// has a zero before the decimal point.
+struct Inspect {
+    length: usize,
+    whole: bool,
+    negative: bool,
+    digits: bool,
+    zero: bool,
+    point: bool,
+    /// The number of digits before the decimal point.
+    whole_digits: usize,
+    zero_before_point: bool,
+}
+
+impl Inspect {
+    fn negative_zero(&self) -> bool {
+        self.negative && self.digits && self.zero
+    }
+
+    fn leading_zero(&self) -> bool {
+        self.point && self.whole_digits == 1 && self.zero_before_point
+    }
+}
+
+impl Default for Inspect {
+    fn default() -> Self {
+        Self {
+            length: 0,
+            whole: true,
+            negative: false,
+            digits: false,
+            zero: true,
+            point: false,
+            whole_digits: 0,
+            zero_before_point: true,
+        }
+    }
+}
+
+impl Write for Inspect {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.length += s.chars().count();
+        for c in s.chars() {
+            self.whole &= c.is_ascii_digit() || c == '-' || c == '+';
+            self.negative |= c == '-';
+            self.digits |= c.is_ascii_digit();
+            self.zero &= !c.is_ascii_digit() || c == '0';
+            if c == '.' {
+                self.point = true;
+            } else if c.is_ascii_digit() && !self.point {
+                self.whole_digits += 1;
+                self.zero_before_point &= c == '0';
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Writes to `inner`, dropping the sign if `skip_sign` is set and the zero before the decimal
+/// point if `skip_zero` is set, replacing the decimal point with `decimal_char` and the digits
+/// with those counting up from `zero`.
+struct Adjust<W> {
+    inner: W,
+    skip_sign: bool,
+    skip_zero: bool,
+    decimal_char: char,
+    zero: char,
+}
+
+impl<W: Write> Write for Adjust<W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        if !self.skip_sign && !self.skip_zero && self.decimal_char == '.' && self.zero == '0' {
+            return self.inner.write_str(s);
+        }
+
+        for c in s.chars() {
+            match c {
+                '-' if self.skip_sign => self.skip_sign = false,
+                '0' if self.skip_zero => self.skip_zero = false,
+                '.' => self.inner.write_char(self.decimal_char)?,
+                '0'..='9' => self
+                    .inner
+                    .write_char(numeral(self.zero, c as u32 - '0' as u32))?,
+                c => self.inner.write_char(c)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::string::ToString;
+
+    use super::*;
+
+    fn float(num_decimal_places: Option<usize>, value: f64) -> std::string::String {
+        Float::new(0, num_decimal_places, ' ', false, value).to_string()
+    }
+
+    #[test]
+    fn adds_a_decimal_point() {
+        assert_eq!("100.0", float(None, 100.0));
+        assert_eq!(
+            "-3.0",
+            Float::new(0, None, ' ', false, -3.0_f32).to_string()
+        );
+        assert_eq!("3.5", float(None, 3.5));
+        assert_eq!("0.0000001", float(None, 1e-7));
+        assert_eq!("inf", float(None, f64::INFINITY));
+    }
+
+    #[test]
+    fn pads() {
+        assert_eq!(
+            "  100.0",
+            Float::new(7, None, ' ', false, 100.0).to_string()
+        );
+        assert_eq!("**3.5", Float::new(5, None, '*', false, 3.5).to_string());
+        assert_eq!("100.0", Float::new(3, None, ' ', false, 100.0).to_string());
+        assert_eq!(
+            "  1.23",
+            Float::new(6, Some(2), ' ', false, 1.23456).to_string()
+        );
+    }
+
+    #[test]
+    fn pads_left_justified() {
+        assert_eq!(
+            "3.5**",
+            Float::new(5, None, '*', false, 3.5)
+                .left_justify()
+                .to_string()
+        );
+        assert_eq!(
+            "-.50  ",
+            Float::new(6, Some(2), ' ', false, -0.5)
+                .drop_leading_zero()
+                .left_justify()
+                .to_string()
+        );
+        assert_eq!(
+            "100.0",
+            Float::new(3, None, ' ', false, 100.0)
+                .left_justify()
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn prints_decimal_places() {
+        assert_eq!("100.00", float(Some(2), 100.0));
+        assert_eq!("1.23", float(Some(2), 1.23456));
+        assert_eq!("2", float(Some(0), 1.5));
+    }
+
+    #[test]
+    fn prints_the_sign() {
+        assert_eq!(
+            "+1.23",
+            Float::new(0, Some(2), ' ', true, 1.23456).to_string()
+        );
+        assert_eq!(
+            "-1.23",
+            Float::new(0, Some(2), ' ', true, -1.23456).to_string()
+        );
+        assert_eq!(" +100.0", Float::new(7, None, ' ', true, 100.0).to_string());
+    }
+
+    #[test]
+    fn prints_other_numerals() {
+        let float = Float::new(7, Some(2), ' ', false, -12.5)
+            .decimal_char('٫')
+            .zero_digit('٠');
+        assert_eq!(" -١٢٫٥٠", float.to_string());
+    }
+
+    #[test]
+    fn prints_the_decimal_char() {
+        let float = Float::new(7, Some(2), ' ', false, -1234.5).decimal_char(',');
+        assert_eq!("-1234,50", float.to_string());
+        let float = Float::new(6, None, '*', false, 3.0).decimal_char(',');
+        assert_eq!("***3,0", float.to_string());
+    }
+
+    #[test]
+    fn drops_the_leading_zero() {
+        let float = |value| Float::new(0, Some(2), ' ', false, value).drop_leading_zero();
+        assert_eq!(".50", float(0.5).to_string());
+        assert_eq!("-.50", float(-0.5).to_string());
+        assert_eq!("1.50", float(1.5).to_string());
+        assert_eq!("10.00", float(10.0).to_string());
+        assert_eq!(".00", float(-0.001).to_string());
+        let float = Float::new(5, Some(2), ' ', true, 0.5).drop_leading_zero();
+        assert_eq!(" +.50", float.to_string());
+    }
+
+    #[test]
+    fn drops_the_sign_of_negative_zero() {
+        assert_eq!("0.00", float(Some(2), -0.0));
+        assert_eq!("0.00", float(Some(2), 0.0));
+        assert_eq!("0.00", float(Some(2), -0.001));
+        assert_eq!("  0.0", Float::new(5, None, ' ', false, -0.0).to_string());
+        assert_eq!("-0.01", float(Some(2), -0.006));
+        assert_eq!(
+            "-0.00",
+            Float::new(0, Some(2), ' ', true, -0.001).to_string()
+        );
+        assert_eq!("-inf", float(None, f64::NEG_INFINITY));
+    }
+
+    #[test]
+    fn rounds_half_up() {
+        let float = |num_decimal_places, value: f64| {
+            Float::new(0, Some(num_decimal_places), ' ', false, value)
+                .round_half_up()
+                .to_string()
+        };
+        assert_eq!(
+            "2.67",
+            Float::new(0, Some(2), ' ', false, 2.675).to_string()
+        );
+        assert_eq!("2.68", float(2, 2.675));
+        assert_eq!("-2.68", float(2, -2.675));
+        assert_eq!("0.13", float(2, 0.125));
+        assert_eq!("1", float(0, 0.5));
+        assert_eq!("3", float(0, 2.5));
+        assert_eq!("-1", float(0, -0.5));
+        assert_eq!("2.67", float(2, 2.674999));
+        assert_eq!("10.00", float(2, 9.995));
+        assert_eq!("100", float(0, 99.5));
+        assert_eq!("1.50", float(2, 1.5));
+        assert_eq!("0.00", float(2, -0.001));
+        assert_eq!("inf", float(2, f64::INFINITY));
+        assert_eq!(
+            " +2.68",
+            Float::new(6, Some(2), ' ', true, 2.675)
+                .round_half_up()
+                .to_string()
+        );
+    }
+}
@@ -0,0 +1,71 @@
+//! Adapts `std::io::Write` writers so the directives can write to them with `core::fmt::Write`.
+use core::fmt;
+use std::io;
+
+/// Wraps an `io::Write`, keeping hold of the `io::Error` if a write fails so it can be
+/// returned to the caller.
+#[derive(Debug)]
+pub struct IoWriter<W> {
+    inner: W,
+    error: Option<io::Error>,
+}
+
+impl<W: io::Write> IoWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self { inner, error: None }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+
+    /// Returns the error that caused a write to fail. If the underlying writer didn't fail
+    /// then a formatting trait implementation must have returned an error.
+    pub fn into_error(self) -> io::Error {
+        self.error
+            .unwrap_or_else(|| io::Error::other("formatter error"))
+    }
+}
+
+impl<W: io::Write> fmt::Write for IoWriter<W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.inner.write_all(s.as_bytes()).map_err(|err| {
+            self.error = Some(err);
+            fmt::Error
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::vec::Vec;
+
+    use super::*;
+    use core::fmt::Write;
+
+    struct Full;
+
+    impl io::Write for Full {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            Err(io::Error::new(io::ErrorKind::WriteZero, "full"))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn writes_bytes() {
+        let mut writer = IoWriter::new(Vec::new());
+        write!(writer, "zork {}", 42).unwrap();
+        assert_eq!(b"zork 42".to_vec(), writer.into_inner());
+    }
+
+    #[test]
+    fn keeps_error() {
+        let mut writer = IoWriter::new(Full);
+        assert!(write!(writer, "zork").is_err());
+        assert_eq!(io::ErrorKind::WriteZero, writer.into_error().kind());
+    }
+}
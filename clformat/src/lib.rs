@@ -1,9 +1,80 @@
 #![no_std]
-pub use clformat_macro::clformat;
+pub use clformat_macro::{
+    clformat, clformat_args, clformat_compile, clformat_writeln, try_clformat,
+};
 
+#[cfg(feature = "alloc")]
+extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
+
+mod arg_check;
+mod byte_writer;
+mod choice;
+mod cl_display;
+mod column_writer;
 mod decimal;
+mod duration;
+mod float;
+#[cfg(feature = "std")]
+mod io_writer;
+mod list;
+mod locale;
 mod num;
+mod param;
 mod ruler;
+#[cfg(feature = "std")]
+mod runtime;
+mod truthy;
 
+pub use byte_writer::ByteWriter;
+pub use choice::Choice;
+pub use cl_display::{Cl, ClDisplay, ClListItem, Joined};
+pub use column_writer::ColumnWriter;
 pub use decimal::Decimal;
-pub use ruler::Ruler;
+pub use duration::Elapsed;
+pub use float::Float;
+#[cfg(feature = "std")]
+pub use io_writer::IoWriter;
+pub use list::Times;
+pub use locale::Locale;
+pub use ruler::{Ruler, Truncate};
+#[cfg(feature = "std")]
+pub use runtime::{format_args_to_string, format_to_string, Arg, FormatError};
+pub use truthy::Truthy;
+
+#[doc(hidden)]
+pub mod __private {
+    pub use crate::arg_check::{debug_arg, display_arg, float_arg, list_arg, option_arg};
+    pub use crate::byte_writer::{BytesKind, FmtWriterKind, OtherKind, Writer};
+    pub use crate::cl_display::{ClDisplayKind, DisplayKind};
+    pub use crate::list::{BorrowedListKind, ClonedListKind};
+    pub use crate::param::ParamArg;
+
+    #[cfg(feature = "std")]
+    pub use crate::runtime::write_iteration;
+    #[cfg(feature = "alloc")]
+    pub use alloc::string::String;
+}
+
+/// Writes `~{~}`, whose body is interpreted by the runtime formatter.
+#[doc(hidden)]
+#[cfg(feature = "std")]
+#[macro_export]
+macro_rules! __write_iteration {
+    ($($argument:expr),* $(,)?) => {
+        $crate::__private::write_iteration($($argument),*)
+    };
+}
+
+/// Without `std` there is no runtime formatter, so `~{~}` is rejected.
+#[doc(hidden)]
+#[cfg(not(feature = "std"))]
+#[macro_export]
+macro_rules! __write_iteration {
+    ($($argument:expr),* $(,)?) => {
+        ::core::compile_error!(
+            "`~{~}` needs the `std` feature of clformat, as its body is formatted at runtime"
+        )
+    };
+}
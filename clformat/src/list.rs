@@ -0,0 +1,90 @@
+//! Lists given to `~{...~}`. They are borrowed so the caller keeps ownership of them.
+use core::ops::Range;
+
+/// Repeats the body of `~{...~}` a number of times, for when there is no list to iterate.
+///
+/// The elements are the indices of the passes, so `~{x~}` with `Times(3)` prints `xxx` and
+/// `~{~D~^ ~}` prints `0 1 2`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Times(pub usize);
+
+impl IntoIterator for Times {
+    type Item = usize;
+    type IntoIter = Range<usize>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        0..self.0
+    }
+}
+
+impl IntoIterator for &Times {
+    type Item = usize;
+    type IntoIter = Range<usize>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        0..self.0
+    }
+}
+
+/// The macro calls `(&list).clformat_list()` when the list is a variable or a field. Method
+/// resolution picks [`BorrowedListKind`] if a reference to the list can be iterated, as for
+/// `Vec`, arrays and maps, otherwise [`ClonedListKind`] clones an iterator held in a variable,
+/// which can't be moved out of the borrow.
+#[doc(hidden)]
+pub trait BorrowedListKind {
+    type Iter: Iterator;
+
+    fn clformat_list(self) -> Self::Iter;
+}
+
+impl<'a, T: ?Sized> BorrowedListKind for &'a T
+where
+    &'a T: IntoIterator,
+{
+    type Iter = <&'a T as IntoIterator>::IntoIter;
+
+    fn clformat_list(self) -> Self::Iter {
+        self.into_iter()
+    }
+}
+
+#[doc(hidden)]
+pub trait ClonedListKind {
+    type Iter: Iterator;
+
+    fn clformat_list(self) -> Self::Iter;
+}
+
+impl<T: Iterator + Clone> ClonedListKind for &T {
+    type Iter = T;
+
+    fn clformat_list(self) -> Self::Iter {
+        self.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn borrows_collections() {
+        let list = [1, 2, 3];
+        let mut iter = (&list).clformat_list();
+        assert_eq!(Some(&1), iter.next());
+        assert_eq!(3, list.len());
+    }
+
+    #[test]
+    fn repeats() {
+        assert_eq!(3, Times(3).into_iter().count());
+        assert_eq!(Some(2), (&Times(3)).clformat_list().last());
+    }
+
+    #[test]
+    fn clones_iterators() {
+        let range = 1..3;
+        assert_eq!(3, (&range).clformat_list().sum::<i32>());
+        assert_eq!(1..3, range);
+    }
+}
@@ -0,0 +1,52 @@
+//! Conventions for formatting numbers, which can be given for a whole `clformat!` call.
+
+/// The characters used to format numbers, given before the format string as in
+/// `clformat!(nil, locale = GERMAN, "~:D", 1234567)`.
+///
+/// `~D` uses `thousands` and `grouping` when the format string doesn't give its comma
+/// character and interval, and `~F` uses `decimal` for the decimal point. Both print their
+/// digits counting up from `zero`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Locale {
+    pub thousands: char,
+    pub decimal: char,
+    pub grouping: usize,
+    /// The digit zero of the numeral system, such as `'٠'` for Arabic-Indic digits. The other
+    /// digits follow it, as they do in every decimal numeral system in Unicode.
+    pub zero: char,
+}
+
+impl Locale {
+    /// The conventions used without a locale, such as `1,234.5`.
+    pub const DEFAULT: Self = Self {
+        thousands: ',',
+        decimal: '.',
+        grouping: 3,
+        zero: '0',
+    };
+}
+
+impl Default for Locale {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// The character for a decimal `digit` in the numeral system starting at `zero`, or the ASCII
+/// digit if `zero` isn't followed by nine more characters.
+pub(crate) fn numeral(zero: char, digit: u32) -> char {
+    char::from_u32(zero as u32 + digit).unwrap_or(char::from(b'0' + digit as u8))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numerals() {
+        assert_eq!('7', numeral('0', 7));
+        assert_eq!('٣', numeral('٠', 3));
+        assert_eq!('९', numeral('०', 9));
+        assert_eq!('9', numeral(char::MAX, 9));
+    }
+}
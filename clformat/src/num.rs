@@ -1,62 +1,187 @@
 //! Trait and implementations to help us format numbers of different types
 
-pub trait Num: Copy + PartialOrd {
-    fn divide_by(self, divisor: isize) -> isize;
-    fn multiply_by(self, factor: isize) -> Self;
-    fn subtract_by(self, num: isize) -> Self;
-    fn zero() -> Self;
-    fn one() -> Self;
-    fn as_u8(self) -> u8;
-    fn as_usize(self) -> usize;
+#[diagnostic::on_unimplemented(
+    message = "`~D`, `~X`, `~O` and `~B` require an integer or float argument",
+    label = "this argument is formatted as a number"
+)]
+pub trait Num {
+    fn is_negative(&self) -> bool;
+    /// The absolute value of the number. Every integer type fits in a `u128` so
+    /// the digits can be extracted without truncating large values.
+    fn magnitude(&self) -> u128;
+    /// The absolute value of a number that may not fit in a `u128`, or `None` when
+    /// [`Num::magnitude`] holds all of it.
+    #[cfg(feature = "bigint")]
+    fn big_magnitude(&self) -> Option<num_bigint::BigUint> {
+        None
+    }
+    /// The text printed in place of the digits for a float that is infinite or not a
+    /// number, after the sign.
+    fn non_finite(&self) -> Option<&'static str> {
+        None
+    }
 }
 
-macro_rules! impl_num {
+/// Numbers behind references and smart pointers are formatted as the number itself.
+macro_rules! impl_pointer {
+    ($($t:ty),*) => {
+        $(
+            impl<T: Num + ?Sized> Num for $t {
+                fn is_negative(&self) -> bool {
+                    (**self).is_negative()
+                }
+
+                fn magnitude(&self) -> u128 {
+                    (**self).magnitude()
+                }
+
+                #[cfg(feature = "bigint")]
+                fn big_magnitude(&self) -> Option<num_bigint::BigUint> {
+                    (**self).big_magnitude()
+                }
+
+                fn non_finite(&self) -> Option<&'static str> {
+                    (**self).non_finite()
+                }
+            }
+        )*
+    };
+}
+
+impl_pointer!(&T);
+#[cfg(feature = "alloc")]
+impl_pointer!(alloc::boxed::Box<T>, alloc::rc::Rc<T>, alloc::sync::Arc<T>);
+
+macro_rules! impl_signed {
     ($t:ty) => {
         impl Num for $t {
-            fn divide_by(self, divisor: isize) -> isize {
-                self as isize / divisor
+            fn is_negative(&self) -> bool {
+                *self < 0
             }
 
-            fn multiply_by(self, factor: isize) -> Self {
-                self * factor as Self
+            fn magnitude(&self) -> u128 {
+                self.unsigned_abs() as u128
             }
+        }
+    };
+}
 
-            fn subtract_by(self, num: isize) -> Self {
-                self - num as Self
+macro_rules! impl_unsigned {
+    ($t:ty) => {
+        impl Num for $t {
+            fn is_negative(&self) -> bool {
+                false
             }
 
-            fn as_u8(self) -> u8 {
-                self as u8
+            fn magnitude(&self) -> u128 {
+                *self as u128
             }
+        }
+    };
+}
 
-            fn as_usize(self) -> usize {
-                self as usize
+/// Floats are rounded to the nearest integer, with halves rounded away from zero. Infinities
+/// and NaN are printed as Rust prints them.
+macro_rules! impl_float {
+    ($t:ty) => {
+        impl Num for $t {
+            fn is_negative(&self) -> bool {
+                // Anything that rounds to zero is printed without a sign.
+                *self <= -0.5
             }
 
-            fn zero() -> Self {
-                0 as Self
+            fn magnitude(&self) -> u128 {
+                if !self.is_finite() {
+                    return 0;
+                }
+                // `round` needs `std`, so the fraction is compared with a half. Unlike adding
+                // the half before truncating, taking the fraction is exact.
+                let abs = self.abs();
+                let whole = abs as u128;
+                whole.saturating_add(u128::from(abs - whole as $t >= 0.5))
             }
 
-            fn one() -> Self {
-                1 as Self
+            fn non_finite(&self) -> Option<&'static str> {
+                if self.is_nan() {
+                    Some("NaN")
+                } else if self.is_infinite() {
+                    Some("inf")
+                } else {
+                    None
+                }
             }
         }
     };
 }
 
-impl_num!(isize);
-impl_num!(i8);
-impl_num!(i16);
-impl_num!(i32);
-impl_num!(i64);
-impl_num!(i128);
-
-impl_num!(f32);
-impl_num!(f64);
-
-impl_num!(usize);
-impl_num!(u8);
-impl_num!(u16);
-impl_num!(u32);
-impl_num!(u64);
-impl_num!(u128);
+impl_signed!(isize);
+impl_signed!(i8);
+impl_signed!(i16);
+impl_signed!(i32);
+impl_signed!(i64);
+impl_signed!(i128);
+
+impl_float!(f32);
+impl_float!(f64);
+
+impl_unsigned!(usize);
+impl_unsigned!(u8);
+impl_unsigned!(u16);
+impl_unsigned!(u32);
+impl_unsigned!(u64);
+impl_unsigned!(u128);
+
+/// Big integers saturate [`Num::magnitude`], and give every digit through
+/// [`Num::big_magnitude`].
+#[cfg(feature = "bigint")]
+mod bigint {
+    use num_bigint::{BigInt, BigUint, Sign};
+
+    use super::Num;
+
+    impl Num for BigUint {
+        fn is_negative(&self) -> bool {
+            false
+        }
+
+        fn magnitude(&self) -> u128 {
+            u128::try_from(self).unwrap_or(u128::MAX)
+        }
+
+        fn big_magnitude(&self) -> Option<BigUint> {
+            Some(self.clone())
+        }
+    }
+
+    impl Num for BigInt {
+        fn is_negative(&self) -> bool {
+            self.sign() == Sign::Minus
+        }
+
+        fn magnitude(&self) -> u128 {
+            Num::magnitude(BigInt::magnitude(self))
+        }
+
+        fn big_magnitude(&self) -> Option<BigUint> {
+            Some(BigInt::magnitude(self).clone())
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn saturates_the_magnitude() {
+            let big = BigUint::from(u128::MAX) * 10u8;
+            assert_eq!(u128::MAX, Num::magnitude(&big));
+            assert_eq!(Some(big.clone()), big.big_magnitude());
+
+            let negative = -BigInt::from(42);
+            assert!(negative.is_negative());
+            assert_eq!(42, Num::magnitude(&negative));
+            assert_eq!(Some(BigUint::from(42u8)), negative.big_magnitude());
+            assert_eq!(None, 42.big_magnitude());
+        }
+    }
+}
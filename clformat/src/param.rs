@@ -0,0 +1,63 @@
+//! Numeric parameters that a directive takes from the arguments with `V`.
+
+/// Converts the argument for a `V` parameter to the parameter. As Common Lisp does for
+/// widths, negative numbers count as 0, and numbers too big for a `usize` are the largest
+/// `usize` rather than wrapping.
+#[doc(hidden)]
+#[diagnostic::on_unimplemented(
+    message = "a `V` parameter requires an integer argument",
+    label = "this argument is a parameter of the directive"
+)]
+pub trait ParamArg {
+    fn param(&self) -> usize;
+}
+
+impl<T: ParamArg + ?Sized> ParamArg for &T {
+    fn param(&self) -> usize {
+        (**self).param()
+    }
+}
+
+macro_rules! impl_signed {
+    ($($t:ty),*) => {
+        $(
+            impl ParamArg for $t {
+                fn param(&self) -> usize {
+                    if *self < 0 {
+                        0
+                    } else {
+                        usize::try_from(*self).unwrap_or(usize::MAX)
+                    }
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! impl_unsigned {
+    ($($t:ty),*) => {
+        $(
+            impl ParamArg for $t {
+                fn param(&self) -> usize {
+                    usize::try_from(*self).unwrap_or(usize::MAX)
+                }
+            }
+        )*
+    };
+}
+
+impl_signed!(isize, i8, i16, i32, i64, i128);
+impl_unsigned!(usize, u8, u16, u32, u64, u128);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clamps_to_a_usize() {
+        assert_eq!(5, 5i32.param());
+        assert_eq!(0, (-1i32).param());
+        assert_eq!(0, ParamArg::param(&&i64::MIN));
+        assert_eq!(usize::MAX, u128::MAX.param());
+    }
+}
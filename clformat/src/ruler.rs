@@ -1,5 +1,9 @@
 use core::fmt::Write;
 
+/// Measures the number of columns taken by the text written to it.
+///
+/// Columns are counted as characters, or as their display width with the `unicode-width`
+/// feature so that wide characters such as CJK take up two columns.
 #[derive(Debug, Default)]
 pub struct Ruler {
     length: usize,
@@ -7,15 +11,109 @@ pub struct Ruler {
 
 impl Write for Ruler {
     fn write_str(&mut self, s: &str) -> core::fmt::Result {
-        self.length += s.len();
+        self.length += columns(s);
         Ok(())
     }
 }
 
+#[cfg(not(feature = "unicode-width"))]
+fn columns(s: &str) -> usize {
+    s.chars().count()
+}
+
+#[cfg(feature = "unicode-width")]
+fn columns(s: &str) -> usize {
+    unicode_width::UnicodeWidthStr::width(s)
+}
+
+#[cfg(not(feature = "unicode-width"))]
+fn char_columns(_: char) -> usize {
+    1
+}
+
+#[cfg(feature = "unicode-width")]
+fn char_columns(c: char) -> usize {
+    unicode_width::UnicodeWidthChar::width(c).unwrap_or(0)
+}
+
+/// Writes the text written to it to `inner` until it reaches `max_columns`, dropping the
+/// rest. A character that would go past the limit is dropped whole, as is everything after it.
+#[derive(Debug)]
+pub struct Truncate<W> {
+    inner: W,
+    remaining: usize,
+    full: bool,
+}
+
+impl<W: Write> Truncate<W> {
+    pub fn new(inner: W, max_columns: usize) -> Self {
+        Self {
+            inner,
+            remaining: max_columns,
+            full: false,
+        }
+    }
+}
+
+impl<W: Write> Write for Truncate<W> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        if self.full {
+            return Ok(());
+        }
+
+        // The end of the last character that fits.
+        let mut end = s.len();
+        for (idx, c) in s.char_indices() {
+            let width = char_columns(c);
+            if width > self.remaining {
+                end = idx;
+                self.full = true;
+                break;
+            }
+            self.remaining -= width;
+        }
+
+        self.inner.write_str(&s[..end])
+    }
+}
+
 impl Ruler {
     pub fn length(&self) -> usize {
         self.length
     }
+
+    /// The columns to cut the measured text to, if it is wider than `max_columns`, leaving
+    /// a column for the character that marks it as cut.
+    pub fn truncation(&self, max_columns: usize) -> Option<usize> {
+        (self.length > max_columns).then(|| max_columns.saturating_sub(1))
+    }
+
+    /// The number of pad characters needed to bring the measured text up to at least
+    /// `min_columns`. At least `min_pad` characters are inserted, then more are added
+    /// `col_inc` at a time.
+    pub fn padding(&self, min_columns: usize, col_inc: usize, min_pad: usize) -> usize {
+        let length = self.length + min_pad;
+        if length >= min_columns {
+            min_pad
+        } else {
+            let col_inc = col_inc.max(1);
+            min_pad + (min_columns - length).div_ceil(col_inc) * col_inc
+        }
+    }
+
+    /// The number of pad characters needed to justify the measured text in a field of
+    /// `min_columns`. The field is widened `col_inc` columns at a time until the text and
+    /// at least `min_pad` pad characters fit.
+    pub fn justify_padding(&self, min_columns: usize, col_inc: usize, min_pad: usize) -> usize {
+        let length = self.length + min_pad;
+        let width = if length <= min_columns {
+            min_columns
+        } else {
+            let col_inc = col_inc.max(1);
+            min_columns + (length - min_columns).div_ceil(col_inc) * col_inc
+        };
+        width - self.length
+    }
 }
 
 #[cfg(test)]
@@ -44,4 +142,85 @@ mod tests {
 
         assert_eq!(s.len(), ruler.length);
     }
+
+    #[test]
+    fn counts_characters() {
+        let mut ruler = Ruler::default();
+        write!(ruler, "héllo").unwrap();
+        assert_eq!(5, ruler.length());
+    }
+
+    #[cfg(not(feature = "unicode-width"))]
+    #[test]
+    fn counts_wide_characters() {
+        let mut ruler = Ruler::default();
+        write!(ruler, "日本").unwrap();
+        assert_eq!(2, ruler.length());
+    }
+
+    #[cfg(feature = "unicode-width")]
+    #[test]
+    fn counts_wide_characters() {
+        let mut ruler = Ruler::default();
+        write!(ruler, "日本").unwrap();
+        assert_eq!(4, ruler.length());
+    }
+
+    #[test]
+    fn padding() {
+        let mut ruler = Ruler::default();
+        write!(ruler, "zork").unwrap();
+
+        assert_eq!(0, ruler.padding(0, 1, 0));
+        assert_eq!(0, ruler.padding(4, 1, 0));
+        assert_eq!(6, ruler.padding(10, 1, 0));
+        assert_eq!(2, ruler.padding(4, 1, 2));
+        assert_eq!(8, ruler.padding(10, 4, 0));
+        assert_eq!(9, ruler.padding(10, 4, 1));
+    }
+
+    #[test]
+    fn truncates() {
+        let mut s = String::new();
+        let mut truncate = Truncate::new(&mut s, 6);
+        truncate.write_str("zork").unwrap();
+        truncate.write_str("mid").unwrap();
+        truncate.write_str("z").unwrap();
+        assert_eq!("zorkmi", s);
+
+        let mut s = String::new();
+        write!(Truncate::new(&mut s, 3), "héllo").unwrap();
+        assert_eq!("hél", s);
+
+        let mut s = String::new();
+        write!(Truncate::new(&mut s, 10), "zork").unwrap();
+        assert_eq!("zork", s);
+
+        let mut ruler = Ruler::default();
+        write!(ruler, "zork").unwrap();
+        assert_eq!(None, ruler.truncation(4));
+        assert_eq!(Some(2), ruler.truncation(3));
+        assert_eq!(Some(0), ruler.truncation(0));
+    }
+
+    #[cfg(feature = "unicode-width")]
+    #[test]
+    fn truncates_wide_characters() {
+        let mut s = String::new();
+        write!(Truncate::new(&mut s, 3), "日本語").unwrap();
+        assert_eq!("日", s);
+    }
+
+    #[test]
+    fn justify_padding() {
+        let mut ruler = Ruler::default();
+        write!(ruler, "zork").unwrap();
+
+        assert_eq!(0, ruler.justify_padding(0, 1, 0));
+        assert_eq!(6, ruler.justify_padding(10, 1, 0));
+        assert_eq!(2, ruler.justify_padding(4, 1, 2));
+        assert_eq!(6, ruler.justify_padding(10, 4, 2));
+        assert_eq!(1, ruler.justify_padding(2, 3, 1));
+        assert_eq!(4, ruler.justify_padding(2, 3, 2));
+    }
 }
@@ -0,0 +1,919 @@
+//! Formats with format strings that are only known at runtime.
+//!
+//! The format string is parsed with the same parser as the `clformat!` macro, the
+//! directives are then interpreted against the arguments. Only a subset of the directives
+//! are supported:
+//!
+//! - `~A`, including the padding parameters.
+//! - `~D`. The argument is displayed and parsed as an integer, arguments that aren't
+//!   integers are printed as `~A` would print them.
+//! - `~%`.
+//! - `~*`, `~:*` and `~@*`.
+//! - `~{...~}`, `~^` and `~#`. The argument must be an [`Arg::List`].
+//! - `~[...~]`, `~:[...~]` and `~@[...~]`. See [`Arg`] for how the arguments are tested.
+//!
+//! Any other directive returns [`FormatError::Unsupported`].
+use core::fmt::{self, Display, Write};
+use std::format;
+use std::string::{String, ToString};
+use std::vec::Vec;
+
+use clformat_parse::{parse_format_string, Directive, Navigation, Padding, Value};
+
+use crate::{Decimal, Ruler, Truncate};
+
+/// An argument to the runtime formatter.
+#[derive(Clone, Copy)]
+pub enum Arg<'a> {
+    /// A value that is printed with its `Display` implementation.
+    ///
+    /// `~[` parses the displayed value as the index of the choice and `~:[` takes the
+    /// first choice if it displays as `true` and the second if it displays as `false`.
+    Display(&'a dyn Display),
+    /// A list of arguments for `~{`. The empty list is treated as nil by `~@[`.
+    List(&'a [Arg<'a>]),
+}
+
+impl<'a, T: Display> From<&'a T> for Arg<'a> {
+    fn from(value: &'a T) -> Self {
+        Self::Display(value)
+    }
+}
+
+impl fmt::Debug for Arg<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Display(value) => f.debug_tuple("Display").field(&value.to_string()).finish(),
+            Self::List(list) => f.debug_tuple("List").field(list).finish(),
+        }
+    }
+}
+
+/// The reasons a runtime format can fail.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FormatError {
+    /// The format string could not be parsed.
+    Parse(String),
+    /// The format string uses a directive the runtime formatter doesn't support.
+    Unsupported(&'static str),
+    /// There weren't enough arguments for the directives.
+    MissingArgument,
+    /// An argument couldn't be used by the directive it was given to.
+    InvalidArgument(String),
+}
+
+impl Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Parse(err) => write!(f, "{err}"),
+            Self::Unsupported(directive) => {
+                write!(f, "{directive} is not supported by the runtime formatter")
+            }
+            Self::MissingArgument => write!(f, "not enough arguments"),
+            Self::InvalidArgument(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl std::error::Error for FormatError {}
+
+/// Formats the arguments with a format string that is only known at runtime.
+///
+/// ```
+/// let template = String::from("~A has ~:D toes");
+/// let toes = clformat::format_to_string(&template, &[&"Zork", &2000]).unwrap();
+/// assert_eq!("Zork has 2,000 toes", toes);
+/// ```
+pub fn format_to_string(format_string: &str, args: &[&dyn Display]) -> Result<String, FormatError> {
+    let args = args
+        .iter()
+        .map(|arg| Arg::Display(*arg))
+        .collect::<Vec<_>>();
+    format_args_to_string(format_string, &args)
+}
+
+/// Like [`format_to_string`], but the arguments can contain lists for iteration.
+///
+/// ```
+/// use clformat::Arg;
+///
+/// let toes = [Arg::from(&1), Arg::from(&2), Arg::from(&3)];
+/// let result =
+///     clformat::format_args_to_string("~{~A~^, ~}", &[Arg::List(&toes)]).unwrap();
+/// assert_eq!("1, 2, 3", result);
+/// ```
+pub fn format_args_to_string(format_string: &str, args: &[Arg<'_>]) -> Result<String, FormatError> {
+    let directives =
+        parse_format_string(format_string).map_err(|err| FormatError::Parse(err.message))?;
+    let mut output = String::new();
+    Interpreter::new(args).run(&directives, &mut output)?;
+    Ok(output)
+}
+
+/// Writes `~{~}` for the macros, with the body taken from an argument and the elements of
+/// `list` as its arguments. If the body fails, the error is kept in `error` so the macro can
+/// report it.
+pub fn write_iteration<W: Write + ?Sized, T: Display>(
+    writer: &mut W,
+    format_string: &str,
+    body: &dyn Display,
+    list: impl IntoIterator<Item = T>,
+    error: &mut Option<FormatError>,
+) -> fmt::Result {
+    let items = list.into_iter().collect::<Vec<_>>();
+    let items = items
+        .iter()
+        .map(|item| Arg::Display(item))
+        .collect::<Vec<_>>();
+    match format_args_to_string(format_string, &[Arg::Display(body), Arg::List(&items)]) {
+        Ok(output) => writer.write_str(&output),
+        Err(err) => {
+            *error = Some(err);
+            Err(fmt::Error)
+        }
+    }
+}
+
+/// Whether the directives ran to the end or hit a `~^`.
+#[derive(Debug, PartialEq, Eq)]
+enum Flow {
+    Continue,
+    Break,
+}
+
+/// Runs the directives, keeping track of the next argument to use.
+#[derive(Clone)]
+struct Interpreter<'a, 'b> {
+    args: &'b [Arg<'a>],
+    cursor: usize,
+    /// The index of the pass of the innermost iteration, for `~#`.
+    pass: Option<usize>,
+}
+
+impl<'a, 'b> Interpreter<'a, 'b> {
+    fn new(args: &'b [Arg<'a>]) -> Self {
+        Self {
+            args,
+            cursor: 0,
+            pass: None,
+        }
+    }
+
+    fn next(&mut self) -> Result<&'b Arg<'a>, FormatError> {
+        let arg = self
+            .args
+            .get(self.cursor)
+            .ok_or(FormatError::MissingArgument)?;
+        self.cursor += 1;
+        Ok(arg)
+    }
+
+    fn remaining(&self) -> usize {
+        self.args.len().saturating_sub(self.cursor)
+    }
+
+    fn display(&mut self) -> Result<String, FormatError> {
+        match self.next()? {
+            Arg::Display(value) => Ok(value.to_string()),
+            Arg::List(_) => Err(FormatError::InvalidArgument(
+                "expected a value, found a list".to_string(),
+            )),
+        }
+    }
+
+    /// Runs the empty part of `~{body~:;empty~}`, which like the choices of `~[` doesn't
+    /// consume the arguments it uses.
+    fn run_empty(
+        &self,
+        empty: &Option<Vec<Directive>>,
+        output: &mut String,
+    ) -> Result<Flow, FormatError> {
+        match empty {
+            Some(empty) => self.clone().run(empty, output),
+            None => Ok(Flow::Continue),
+        }
+    }
+
+    /// The next argument displayed with `{:#}`, for `~#A`.
+    fn display_alternate(&mut self) -> Result<String, FormatError> {
+        match self.next()? {
+            Arg::Display(value) => Ok(format!("{value:#}")),
+            Arg::List(_) => Err(FormatError::InvalidArgument(
+                "expected a value, found a list".to_string(),
+            )),
+        }
+    }
+
+    fn num_value(&mut self, value: &Value<usize>) -> Result<usize, FormatError> {
+        match value {
+            Value::Literal(value) => Ok(*value),
+            Value::FromArg => {
+                let value = self.display()?;
+                value.parse().map_err(|_| {
+                    FormatError::InvalidArgument(format!("expected a number, found `{value}`"))
+                })
+            }
+            Value::ArgCount => Ok(self.remaining()),
+        }
+    }
+
+    fn char_value(&mut self, value: &Value<char>) -> Result<char, FormatError> {
+        match value {
+            Value::Literal(value) => Ok(*value),
+            Value::FromArg | Value::ArgCount => {
+                let value = self.display()?;
+                let mut chars = value.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => Ok(c),
+                    _ => Err(FormatError::InvalidArgument(format!(
+                        "expected a character, found `{value}`"
+                    ))),
+                }
+            }
+        }
+    }
+
+    fn run(&mut self, directives: &[Directive], output: &mut String) -> Result<Flow, FormatError> {
+        for directive in directives {
+            match directive {
+                Directive::Literal(literal) => output.push_str(literal),
+                Directive::LoopIndex => match self.pass {
+                    Some(pass) => write!(output, "{pass}").expect("writing to a String"),
+                    None => {
+                        return Err(FormatError::Parse(
+                            "directive `#` not inside loop".to_string(),
+                        ))
+                    }
+                },
+                Directive::Newline { count, crlf } => {
+                    let count = self.num_value(count)?;
+                    let newline = if *crlf { "\r\n" } else { "\n" };
+                    output.push_str(&newline.repeat(count));
+                }
+                Directive::TildeA {
+                    padding,
+                    print_nil: false,
+                    alternate,
+                    ..
+                } => self.write_padded(padding, *alternate, output)?,
+                Directive::TildeA {
+                    print_nil: true, ..
+                } => return Err(FormatError::Unsupported("~:A")),
+                Directive::TildeS { .. } => return Err(FormatError::Unsupported("~S")),
+                Directive::Write { .. } => return Err(FormatError::Unsupported("~W")),
+                Directive::Decimal {
+                    min_columns,
+                    pad_char,
+                    comma_char,
+                    comma_interval,
+                    secondary_interval,
+                    print_commas,
+                    print_sign,
+                    radix,
+                    prefix,
+                    lowercase,
+                    pad_after_sign,
+                } => {
+                    // Parameters taken from the arguments precede the argument being formatted.
+                    let min_columns = self.num_value(min_columns)?;
+                    let pad_char = self.char_value(pad_char)?;
+                    let comma_char = match comma_char {
+                        Some(comma_char) => self.char_value(comma_char)?,
+                        None => ',',
+                    };
+                    let comma_interval = match comma_interval {
+                        Some(interval) => self.num_value(interval)?,
+                        None => 3,
+                    };
+                    let secondary_interval = match secondary_interval {
+                        Some(interval) => self.num_value(interval)?,
+                        None => comma_interval,
+                    };
+                    let value = self.display()?;
+                    let based = |decimal: Decimal| {
+                        let mut decimal =
+                            decimal.secondary_interval(secondary_interval).radix(*radix);
+                        if *lowercase {
+                            decimal = decimal.lowercase();
+                        }
+                        if *pad_after_sign {
+                            decimal = decimal.pad_after_sign();
+                        }
+                        if *prefix {
+                            decimal.rust_prefix()
+                        } else {
+                            decimal
+                        }
+                    };
+
+                    if let Ok(number) = value.parse::<i128>() {
+                        output.extend(based(Decimal::new(
+                            min_columns,
+                            pad_char,
+                            comma_char,
+                            comma_interval,
+                            *print_commas,
+                            *print_sign,
+                            number,
+                        )));
+                    } else if let Ok(number) = value.parse::<u128>() {
+                        output.extend(based(Decimal::new(
+                            min_columns,
+                            pad_char,
+                            comma_char,
+                            comma_interval,
+                            *print_commas,
+                            *print_sign,
+                            number,
+                        )));
+                    } else {
+                        // Lisp prints anything that isn't an integer as `~A` would.
+                        pad(&value, min_columns, 1, 0, pad_char, true, output);
+                    }
+                }
+                Directive::Float { .. } => return Err(FormatError::Unsupported("~F")),
+                Directive::Align { .. } => return Err(FormatError::Unsupported("~<")),
+                Directive::Captured { .. } => {
+                    return Err(FormatError::Unsupported("captured variables"))
+                }
+                Directive::ArgNav { count, direction } => match direction {
+                    Navigation::Forward => {
+                        for _ in 0..*count {
+                            self.next()?;
+                        }
+                    }
+                    Navigation::Backward => {
+                        self.cursor = self.cursor.checked_sub(*count).ok_or_else(|| {
+                            FormatError::InvalidArgument(format!(
+                                "cannot back up {count} arguments with `~:*`"
+                            ))
+                        })?;
+                    }
+                    Navigation::Absolute => {
+                        if *count > self.args.len() {
+                            return Err(FormatError::InvalidArgument(format!(
+                                "cannot jump to argument {count} with `~@*`, only {} provided",
+                                self.args.len()
+                            )));
+                        }
+                        self.cursor = *count;
+                    }
+                },
+                Directive::Iteration {
+                    sublists: true,
+                    remaining,
+                    ..
+                } => {
+                    return Err(FormatError::Unsupported(if *remaining {
+                        "~:@{"
+                    } else {
+                        "~:{"
+                    }))
+                }
+                Directive::Iteration {
+                    sublists: false,
+                    remaining: true,
+                    max_passes,
+                    directives,
+                    empty,
+                } => {
+                    if self.remaining() == 0 && self.run_empty(empty, output)? == Flow::Break {
+                        return Ok(Flow::Break);
+                    }
+                    let outer_pass = self.pass;
+                    let mut passes = 0;
+                    while self.remaining() > 0 && Some(passes) != *max_passes {
+                        self.pass = Some(passes);
+                        passes += 1;
+                        let cursor = self.cursor;
+                        if self.run(directives, output)? == Flow::Break {
+                            break;
+                        }
+                        // Each pass uses at least one argument, so the loop ends.
+                        self.cursor = self.cursor.max(cursor + 1);
+                    }
+                    self.pass = outer_pass;
+                }
+                Directive::Iteration {
+                    sublists: false,
+                    remaining: false,
+                    max_passes,
+                    directives,
+                    empty,
+                } => {
+                    // `~{~}` takes the body from the argument before the list. It is parsed
+                    // inside braces so that `~^` is allowed.
+                    let body;
+                    let directives = if directives.is_empty() {
+                        let parsed = parse_format_string(&format!("~{{{}~}}", self.display()?))
+                            .map_err(|err| FormatError::Parse(err.message))?;
+                        body = match parsed.as_slice() {
+                            [Directive::Iteration { directives, .. }] => directives.clone(),
+                            _ => {
+                                return Err(FormatError::InvalidArgument(
+                                    "the `~{~}` body must not close the iteration".to_string(),
+                                ))
+                            }
+                        };
+                        &body
+                    } else {
+                        directives
+                    };
+
+                    let list = match self.next()? {
+                        Arg::List(list) => list,
+                        Arg::Display(_) => {
+                            return Err(FormatError::InvalidArgument(
+                                "`~{` expects a list".to_string(),
+                            ))
+                        }
+                    };
+
+                    if list.is_empty() && self.run_empty(empty, output)? == Flow::Break {
+                        return Ok(Flow::Break);
+                    }
+
+                    let mut inner = Interpreter::new(list);
+                    let mut passes = 0;
+                    while inner.remaining() > 0 && Some(passes) != *max_passes {
+                        inner.pass = Some(passes);
+                        passes += 1;
+                        let cursor = inner.cursor;
+                        if inner.run(directives, output)? == Flow::Break {
+                            break;
+                        }
+                        // A body that uses no arguments would loop forever, so skip one.
+                        inner.cursor = inner.cursor.max(cursor + 1);
+                    }
+                }
+                Directive::Break { outer: true, .. } => {
+                    return Err(FormatError::Unsupported("~:^"))
+                }
+                Directive::Break {
+                    outer: false,
+                    params,
+                } => {
+                    let values = params
+                        .iter()
+                        .map(|param| self.num_value(param))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    let done = match values.as_slice() {
+                        [] => self.remaining() == 0,
+                        [n] => *n == 0,
+                        [n, m] => n == m,
+                        [n, m, p] => n <= m && m <= p,
+                        _ => unreachable!("the parser rejects more than three parameters"),
+                    };
+                    if done {
+                        return Ok(Flow::Break);
+                    }
+                }
+                Directive::Conditional {
+                    boolean: true,
+                    choices,
+                    ..
+                } => {
+                    let value = self.display()?;
+                    let choice = match value.as_str() {
+                        "true" => &choices[0],
+                        "false" => &choices[1],
+                        _ => {
+                            return Err(FormatError::InvalidArgument(format!(
+                                "expected `true` or `false`, found `{value}`"
+                            )))
+                        }
+                    };
+
+                    // As with the macro, the arguments used by the choice are not consumed.
+                    if self.clone().run(choice, output)? == Flow::Break {
+                        return Ok(Flow::Break);
+                    }
+                }
+                Directive::Conditional {
+                    boolean: false,
+                    consumes: true,
+                    choices,
+                    ..
+                } => match self.next()? {
+                    Arg::List([]) => (),
+                    arg => {
+                        let inner = [*arg];
+                        let mut inner = Interpreter {
+                            pass: self.pass,
+                            ..Interpreter::new(&inner)
+                        };
+                        if inner.run(&choices[0], output)? == Flow::Break {
+                            return Ok(Flow::Break);
+                        }
+                    }
+                },
+                Directive::Conditional {
+                    boolean: false,
+                    consumes: false,
+                    selector,
+                    choices,
+                    default,
+                } => {
+                    let index = match selector {
+                        Some(selector) => self.num_value(selector)?,
+                        None => {
+                            let value = self.display()?;
+                            value.parse::<usize>().map_err(|_| {
+                                FormatError::InvalidArgument(format!(
+                                    "expected an index, found `{value}`"
+                                ))
+                            })?
+                        }
+                    };
+
+                    if let Some(choice) = choices.get(index).or(default.as_ref()) {
+                        if self.clone().run(choice, output)? == Flow::Break {
+                            return Ok(Flow::Break);
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(Flow::Continue)
+    }
+
+    fn write_padded(
+        &mut self,
+        padding: &Padding,
+        alternate: bool,
+        output: &mut String,
+    ) -> Result<(), FormatError> {
+        let min_columns = self.num_value(&padding.min_columns)?;
+        let col_inc = self.num_value(&padding.col_inc)?;
+        let min_pad = self.num_value(&padding.min_pad)?;
+        let pad_char = self.char_value(&padding.pad_char)?;
+        let max_columns = match &padding.max_columns {
+            Some(max_columns) => Some(self.num_value(max_columns)?),
+            None => None,
+        };
+        let mut value = if alternate {
+            self.display_alternate()?
+        } else {
+            self.display()?
+        };
+        if let Some(max_columns) = max_columns {
+            value = truncate(value, max_columns, pad_char);
+        }
+        pad(
+            &value,
+            min_columns,
+            col_inc,
+            min_pad,
+            pad_char,
+            padding.pad_left,
+            output,
+        );
+        Ok(())
+    }
+}
+
+/// Cuts the value to `max_columns`, ending it with `marker` if it doesn't fit.
+fn truncate(value: String, max_columns: usize, marker: char) -> String {
+    let mut ruler = Ruler::default();
+    let _ = ruler.write_str(&value);
+    match ruler.truncation(max_columns) {
+        Some(cut) => {
+            let mut truncated = String::new();
+            let _ = Truncate::new(&mut truncated, cut).write_str(&value);
+            if max_columns > 0 {
+                truncated.push(marker);
+            }
+            truncated
+        }
+        None => value,
+    }
+}
+
+/// Writes the value padded to at least `min_columns`.
+fn pad(
+    value: &str,
+    min_columns: usize,
+    col_inc: usize,
+    min_pad: usize,
+    pad_char: char,
+    pad_left: bool,
+    output: &mut String,
+) {
+    let mut ruler = Ruler::default();
+    let _ = ruler.write_str(value);
+    let fill = core::iter::repeat_n(pad_char, ruler.padding(min_columns, col_inc, min_pad));
+
+    if pad_left {
+        output.extend(fill);
+        output.push_str(value);
+    } else {
+        output.push_str(value);
+        output.extend(fill);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_values() {
+        assert_eq!(
+            Ok("Hello, Zork! You have 2,000 toes\n".to_string()),
+            format_to_string("Hello, ~A! You have ~:D toes~%", &[&"Zork", &2000])
+        );
+        assert_eq!(
+            Ok("zork  |  42".to_string()),
+            format_to_string("~6A|~4@A", &[&"zork", &42])
+        );
+    }
+
+    #[test]
+    fn formats_alternate_values() {
+        struct Verbose;
+
+        impl Display for Verbose {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str(if f.alternate() { "verbose" } else { "terse" })
+            }
+        }
+
+        assert_eq!(
+            Ok("terse|  verbose".to_string()),
+            format_to_string("~A|~9@#A", &[&Verbose, &Verbose])
+        );
+    }
+
+    #[test]
+    fn prints_newlines() {
+        assert_eq!(
+            Ok("a\n\n\nb\nc".to_string()),
+            format_to_string("a~3%b~%c", &[])
+        );
+        assert_eq!(
+            Ok("a\r\n\r\nb".to_string()),
+            format_to_string("a~2:%b", &[])
+        );
+    }
+
+    #[test]
+    fn prints_other_bases() {
+        assert_eq!(
+            Ok("FF 0o17 1,0000".to_string()),
+            format_to_string("~X ~@O ~,,,4:B", &[&255, &15, &16])
+        );
+        assert_eq!(
+            Ok("ff FF".to_string()),
+            format_to_string("~x ~X", &[&255, &255])
+        );
+    }
+
+    #[test]
+    fn truncates() {
+        assert_eq!(
+            Ok("zork|Frobozz…|..Fro.".to_string()),
+            format_to_string(
+                "~,,,'…,8A|~,,,'…,8A|~6,,,'.,4@A",
+                &[&"zork", &"Frobozz Magic", &"Frobozz"]
+            )
+        );
+    }
+
+    #[test]
+    fn pads_after_the_sign() {
+        assert_eq!(
+            Ok("-  42|  -42".to_string()),
+            format_to_string("~5,,,,,1D|~5D", &[&-42, &-42])
+        );
+    }
+
+    #[test]
+    fn groups_with_a_secondary_interval() {
+        assert_eq!(
+            Ok("12,34,567".to_string()),
+            format_to_string("~,,,3,2:D", &[&1234567])
+        );
+    }
+
+    #[test]
+    fn formats_non_integers_with_tilde_d() {
+        assert_eq!(
+            Ok("  zork".to_string()),
+            format_to_string("~6D", &[&"zork"])
+        );
+    }
+
+    #[test]
+    fn iterates() {
+        let toes = [Arg::from(&1), Arg::from(&2), Arg::from(&3)];
+        assert_eq!(
+            Ok("Toes: 1, 2, 3".to_string()),
+            format_args_to_string("Toes: ~{~A~^, ~}", &[Arg::List(&toes)])
+        );
+    }
+
+    #[test]
+    fn prints_the_loop_index() {
+        let toes = [Arg::from(&"a"), Arg::from(&"b")];
+        assert_eq!(
+            Ok("0: a\n1: b\n".to_string()),
+            format_args_to_string("~{~#: ~A~%~}", &[Arg::List(&toes)])
+        );
+        assert_eq!(
+            Ok("0a 1b".to_string()),
+            format_to_string("~@{~#~A~^ ~}", &[&"a", &"b"])
+        );
+        assert_eq!(
+            Err(FormatError::Parse(
+                "directive `#` not inside loop".to_string()
+            )),
+            format_to_string("~:[~#~;x~]", &[&true])
+        );
+    }
+
+    #[test]
+    fn writes_the_empty_part() {
+        assert_eq!(
+            Ok("none|1 2 ".to_string()),
+            format_args_to_string(
+                "~{~A ~:;none~}|~{~A ~:;none~}",
+                &[Arg::List(&[]), Arg::List(&[Arg::from(&1), Arg::from(&2)])]
+            )
+        );
+        assert_eq!(
+            Ok("no toes|toes".to_string()),
+            format_args_to_string("~{~A~:;no ~A~}|~A", &[Arg::List(&[]), Arg::from(&"toes")])
+        );
+        assert_eq!(
+            Ok("none".to_string()),
+            format_to_string("~@{~A~:;none~}", &[])
+        );
+    }
+
+    #[test]
+    fn iterates_over_remaining_arguments() {
+        assert_eq!(
+            Ok("Toes: 1, 2, 3".to_string()),
+            format_to_string("~A: ~@{~A~^, ~}", &[&"Toes", &1, &2, &3])
+        );
+    }
+
+    #[test]
+    fn limits_passes() {
+        let toes = [Arg::from(&1), Arg::from(&2), Arg::from(&3)];
+        assert_eq!(
+            Ok("1 2 ".to_string()),
+            format_args_to_string("~2{~A ~}", &[Arg::List(&toes)])
+        );
+        assert_eq!(
+            Ok("1 2".to_string()),
+            format_to_string("~2@{~A~^ ~}", &[&1, &2])
+        );
+    }
+
+    #[test]
+    fn iterates_with_body_from_argument() {
+        let toes = [Arg::from(&1), Arg::from(&2), Arg::from(&3)];
+        assert_eq!(
+            Ok("1, 2, 3".to_string()),
+            format_args_to_string("~{~}", &[Arg::from(&"~A~^, "), Arg::List(&toes)])
+        );
+    }
+
+    #[test]
+    fn iterates_without_using_arguments() {
+        let toes = [Arg::from(&1), Arg::from(&2), Arg::from(&3)];
+        assert_eq!(
+            Ok("xxx".to_string()),
+            format_args_to_string("~{x~}", &[Arg::List(&toes)])
+        );
+        assert_eq!(
+            Ok("".to_string()),
+            format_args_to_string("~{~}", &[Arg::from(&""), Arg::List(&toes)])
+        );
+        assert_eq!(Ok("xx".to_string()), format_to_string("~@{x~}", &[&1, &2]));
+    }
+
+    #[test]
+    fn breaks_with_parameters() {
+        assert_eq!(
+            Ok("10 20 ".to_string()),
+            format_to_string("~@{~V^~A ~}", &[&1, &10, &2, &20, &0, &30])
+        );
+        assert_eq!(
+            Ok("1, 2".to_string()),
+            format_to_string("~@{~A~#,1^, ~}", &[&1, &2, &3])
+        );
+        assert_eq!(
+            Ok("100 200 ".to_string()),
+            format_to_string("~@{~10,V,99^~A ~}", &[&1, &100, &2, &200, &50, &500])
+        );
+    }
+
+    #[test]
+    fn conditional_on_arg_count() {
+        let format_string = "~#[none~;one~:;many~]";
+        assert_eq!(Ok("none".to_string()), format_to_string(format_string, &[]));
+        assert_eq!(
+            Ok("one".to_string()),
+            format_to_string(format_string, &[&1])
+        );
+        assert_eq!(
+            Ok("many".to_string()),
+            format_to_string(format_string, &[&1, &2])
+        );
+    }
+
+    #[test]
+    fn conditional_selector() {
+        assert_eq!(Ok("b".to_string()), format_to_string("~1[a~;b~;c~]", &[]));
+        assert_eq!(
+            Ok("c 7".to_string()),
+            format_to_string("~V[a~;b~;c ~A~]", &[&2, &7])
+        );
+    }
+
+    #[test]
+    fn conditionals() {
+        assert_eq!(
+            Ok("zoggle".to_string()),
+            format_to_string("~[zork~;zoggle~:;zoog~]", &[&1])
+        );
+        assert_eq!(
+            Ok("zoog".to_string()),
+            format_to_string("~[zork~;zoggle~:;zoog~]", &[&7])
+        );
+        assert_eq!(
+            Ok("zoggle".to_string()),
+            format_to_string("~:[nork~;zoggle~]", &[&false])
+        );
+        assert_eq!(
+            Ok("42 toes".to_string()),
+            format_args_to_string(
+                "~@[~A toes~]~@[~A fish~]",
+                &[Arg::from(&42), Arg::List(&[])]
+            )
+        );
+    }
+
+    #[test]
+    fn prints_tildes() {
+        assert_eq!(
+            Ok("~1~ ~2~ ~~".to_string()),
+            format_args_to_string(
+                "~{~~~A~~~^ ~} ~2~",
+                &[Arg::List(&[Arg::from(&1), Arg::from(&2)])]
+            )
+        );
+    }
+
+    #[test]
+    fn moves_between_arguments() {
+        assert_eq!(
+            Ok("1 1 3 1".to_string()),
+            format_to_string("~A ~:*~A ~*~A ~@*~A", &[&1, &2, &3])
+        );
+
+        let cases = [
+            ("~*~A", "b"),
+            ("~2*~A", "c"),
+            ("~0*~A", "a"),
+            ("~A~:*~A", "aa"),
+            ("~A~A~2:*~A", "aba"),
+            ("~A~A~0:*~A", "abc"),
+            ("~@*~A", "a"),
+            ("~2@*~A~0@*~A", "ca"),
+            ("~3@*~:*~A", "c"),
+        ];
+        for (format_string, expected) in cases {
+            assert_eq!(
+                Ok(expected.to_string()),
+                format_to_string(format_string, &[&"a", &"b", &"c"]),
+                "{format_string}"
+            );
+        }
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            Err(FormatError::Parse("invalid directive `~Z`".to_string())),
+            format_to_string("~z", &[])
+        );
+        assert_eq!(
+            Err(FormatError::Parse(
+                "unterminated `~{` directive, expected a closing `~}`".to_string()
+            )),
+            format_to_string("~{~A~^, ", &[])
+        );
+        assert_eq!(
+            Err(FormatError::MissingArgument),
+            format_to_string("~A ~A", &[&1])
+        );
+        assert_eq!(
+            Err(FormatError::Unsupported("~F")),
+            format_to_string("~F", &[&1.0])
+        );
+    }
+}
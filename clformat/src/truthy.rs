@@ -0,0 +1,91 @@
+//! Decides which choice of `~:[` an argument selects.
+
+/// Whether an argument to `~:[true~;false~]` is true, selecting the first choice.
+///
+/// `false`, `None`, zero and empty strings are false, much as `nil` is in Lisp. Implement
+/// this for your own types to use them with `~:[` directly.
+#[diagnostic::on_unimplemented(
+    message = "`~:[` requires an argument that implements `clformat::Truthy`",
+    label = "this argument selects the choice of `~:[`"
+)]
+pub trait Truthy {
+    fn is_truthy(&self) -> bool;
+}
+
+impl Truthy for bool {
+    fn is_truthy(&self) -> bool {
+        *self
+    }
+}
+
+impl<T> Truthy for Option<T> {
+    fn is_truthy(&self) -> bool {
+        self.is_some()
+    }
+}
+
+impl Truthy for str {
+    fn is_truthy(&self) -> bool {
+        !self.is_empty()
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl Truthy for alloc::string::String {
+    fn is_truthy(&self) -> bool {
+        !self.is_empty()
+    }
+}
+
+/// Values behind references and smart pointers are as true as the value itself.
+macro_rules! impl_pointer {
+    ($($t:ty),*) => {
+        $(
+            impl<T: Truthy + ?Sized> Truthy for $t {
+                fn is_truthy(&self) -> bool {
+                    (**self).is_truthy()
+                }
+            }
+        )*
+    };
+}
+
+impl_pointer!(&T);
+#[cfg(feature = "alloc")]
+impl_pointer!(alloc::boxed::Box<T>, alloc::rc::Rc<T>, alloc::sync::Arc<T>);
+
+macro_rules! impl_integer {
+    ($($t:ty),*) => {
+        $(
+            impl Truthy for $t {
+                fn is_truthy(&self) -> bool {
+                    *self != 0
+                }
+            }
+        )*
+    };
+}
+
+impl_integer!(isize, i8, i16, i32, i64, i128, usize, u8, u16, u32, u64, u128);
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+
+    #[test]
+    fn tests_values() {
+        assert!(true.is_truthy());
+        assert!(!false.is_truthy());
+        assert!(Some(0).is_truthy());
+        assert!(!None::<i32>.is_truthy());
+        assert!((-1).is_truthy());
+        assert!(!0u8.is_truthy());
+        assert!("zork".is_truthy());
+        assert!(!"".is_truthy());
+        assert!(!(&&"").is_truthy());
+        assert!(!std::rc::Rc::new(0).is_truthy());
+        assert!(std::boxed::Box::new(true).is_truthy());
+    }
+}
@@ -0,0 +1,42 @@
+//! Checks that the code generated by `clformat!` compiles in a `no_std` crate with `alloc`.
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{string::String, vec::Vec};
+use clformat::clformat;
+
+pub fn toes(name: &str, count: usize) -> String {
+    clformat!(nil, "~A has ~:D toes", name, count)
+}
+
+pub fn justified(name: &str) -> String {
+    clformat!(nil, "~10:@<~A~>", name)
+}
+
+pub fn packet(buffer: &mut Vec<u8>, name: &str, count: usize) {
+    clformat!(*buffer, "~A:~5,'0D;", name, count);
+}
+
+pub fn into_fmt_writer<W: core::fmt::Write>(writer: &mut W, count: usize) {
+    clformat!(*writer, "~D", count);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_strings() {
+        assert_eq!("Zork has 2,000 toes", toes("Zork", 2000));
+        assert_eq!("   Zork   ", justified("Zork"));
+    }
+
+    #[test]
+    fn formats_bytes() {
+        let mut buffer = Vec::new();
+        packet(&mut buffer, "zork", 42);
+        packet(&mut buffer, "nork", 7);
+        assert_eq!(b"zork:00042;nork:00007;", buffer.as_slice());
+    }
+}
@@ -1,6 +1,6 @@
 #[cfg(test)]
 mod tests {
-    use clformat::clformat;
+    use clformat::{clformat, clformat_args, clformat_compile, clformat_writeln, try_clformat};
 
     #[test]
     fn it_works() {
@@ -10,9 +10,53 @@ mod tests {
         assert_eq!("Hello, Dr Ponk", x);
     }
 
+    #[test]
+    fn ui() {
+        let cases = trybuild::TestCases::new();
+        cases.compile_fail("ui/*.rs");
+    }
+
+    #[test]
+    fn display_args() {
+        struct Zork {
+            toes: usize,
+        }
+
+        impl std::fmt::Display for Zork {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                write!(f, "<{}>", clformat_args!("Zork has ~:D toes", self.toes))
+            }
+        }
+
+        assert_eq!("<Zork has 2,000 toes>", Zork { toes: 2000 }.to_string());
+
+        let x = vec![1, 2, 3];
+        let args = clformat_args!("~{~A~^, ~}", &x);
+        let mut output = String::new();
+        std::fmt::Write::write_fmt(&mut output, format_args!("{args} and {args}")).unwrap();
+        assert_eq!("1, 2, 3 and 1, 2, 3", output);
+
+        assert_eq!(
+            "abc\ndefg",
+            clformat_args!("~A~<~%~,6:;~A~>", "abc", "defg").to_string()
+        );
+    }
+
+    #[test]
+    fn display_args_evaluates_arguments_once() {
+        let mut count = 0;
+        let mut next = || {
+            count += 1;
+            count
+        };
+        let args = clformat_args!("~D ~{~D~}", next(), vec![1, 2].into_iter());
+        assert_eq!("1 12", args.to_string());
+        assert_eq!("1 12", args.to_string());
+        assert_eq!(1, count);
+    }
+
     #[test]
     fn writer() {
-        use std::fmt::Write;
         let mut write_to_me = String::new();
         let dr = "Dr Nork";
         clformat!(write_to_me, "Hey, ~A", dr);
@@ -20,10 +64,257 @@ mod tests {
         assert_eq!("Hey, Dr Nork", write_to_me);
     }
 
+    #[test]
+    fn reused_buffer() {
+        let mut buffer = String::with_capacity(64);
+        let capacity = buffer.capacity();
+        for (name, toes) in [("Zork", 10), ("Nork", 1000)] {
+            let entry = clformat!(&mut buffer as string, "~A: ~:D", name, toes);
+            assert!(entry.starts_with(name));
+        }
+        assert_eq!("Nork: 1,000", buffer);
+        assert_eq!(capacity, buffer.capacity());
+
+        let result = try_clformat!(&mut buffer as string, "~5,'0D", 42);
+        assert_eq!(Ok("00042"), result);
+    }
+
+    /// A writer that fails after the first write.
+    #[derive(Default)]
+    struct Flaky {
+        written: String,
+        writes: usize,
+    }
+
+    impl std::fmt::Write for Flaky {
+        fn write_str(&mut self, s: &str) -> std::fmt::Result {
+            self.writes += 1;
+            if self.writes > 1 {
+                return Err(std::fmt::Error);
+            }
+            self.written.push_str(s);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn try_writer() {
+        let mut flaky = Flaky::default();
+        let result = try_clformat!(flaky, "~A", "zork");
+        assert!(result.is_ok());
+
+        let result = try_clformat!(flaky, "~A", "nork");
+        assert_eq!(Some(std::fmt::Error), result.err());
+        assert_eq!("zork", flaky.written);
+
+        let x = try_clformat!(nil, "Hello, ~A", "Dr Ponk");
+        assert_eq!(Ok("Hello, Dr Ponk".to_string()), x);
+    }
+
+    #[test]
+    #[should_panic(expected = "clformat: write failed for format string \"~A ~A\"")]
+    fn panic_message() {
+        let mut flaky = Flaky::default();
+        clformat!(flaky, "~A ~A", "zork", "nork");
+    }
+
+    #[test]
+    fn panic_message_locates_the_call() {
+        let line = line!() + 3;
+        let panic = std::panic::catch_unwind(|| {
+            let mut flaky = Flaky::default();
+            clformat!(flaky, "~A ~A", "zork", "nork");
+        })
+        .unwrap_err();
+        let message = panic.downcast_ref::<String>().unwrap();
+        assert!(
+            message.contains(&format!("at tests/src/lib.rs:{line}:13: ")),
+            "{message}"
+        );
+    }
+
     #[test]
     fn smoke_stdout() {
         let dr = "Dr Ponk";
         clformat!(t, "~%Hello, ~A~%", dr);
+        clformat!(t, "~20:@<Hello, ~A~>~%", dr);
+    }
+
+    #[test]
+    fn byte_buffer() {
+        let mut bytes: Vec<u8> = Vec::new();
+        clformat!(bytes, "~A: ~:D", "zork", 4200);
+        clformat!(bytes, "; ~A", "crème");
+        assert_eq!("zork: 4,200; crème".as_bytes(), bytes);
+
+        let mut bytes = std::collections::VecDeque::new();
+        clformat!(bytes, "~5,'0D", 42);
+        assert_eq!(b"00042".to_vec(), Vec::from(bytes));
+    }
+
+    #[test]
+    fn io_writer() {
+        let mut bytes: Vec<u8> = Vec::new();
+        clformat!(io!(bytes), "~A: ~:D", "zork", 4200);
+        assert_eq!(b"zork: 4,200".to_vec(), bytes);
+
+        let mut buffer = [0_u8; 4];
+        let mut slice = buffer.as_mut_slice();
+        let result = try_clformat!(io!(slice), "~A", "zork nork");
+        assert_eq!(
+            Some(std::io::ErrorKind::WriteZero),
+            result.err().map(|err| err.kind())
+        );
+        assert_eq!(b"zork", &buffer);
+    }
+
+    #[test]
+    fn elapsed() {
+        let took = std::time::Duration::from_millis(5_025_500);
+        assert_eq!(
+            "took 01:23:45.500",
+            clformat!(nil, "took ~A", clformat::Elapsed(took))
+        );
+    }
+
+    #[test]
+    fn literal_braces() {
+        assert_eq!("a{b}c", clformat!(nil, "a{b}c"));
+        assert_eq!("{} 100% {{", clformat!(nil, "{} ~A% {{", 100));
+        assert_eq!("50% {done}", clformat!(nil, "50% {done}"));
+        assert_eq!("{0} {:?} 1", clformat!(nil, "{0} {:?} ~A", 1));
+    }
+
+    #[test]
+    fn aesthetic_padding() {
+        assert_eq!("hi        ", clformat!(nil, "~10A", "hi"));
+        assert_eq!("hi********|", clformat!(nil, "~10,,,'*A|", "hi"));
+        assert_eq!("hi   ", clformat!(nil, "~4,,3A", "hi"));
+        assert_eq!("hi    ", clformat!(nil, "~5,4A", "hi"));
+        assert_eq!("toolong", clformat!(nil, "~4A", "toolong"));
+        assert_eq!("42---", clformat!(nil, "~V,,,VA", 5, '-', 42));
+    }
+
+    #[test]
+    fn aesthetic_padding_left() {
+        assert_eq!("        hi", clformat!(nil, "~10@A", "hi"));
+        assert_eq!("********hi|", clformat!(nil, "~10,,,'*@A|", "hi"));
+        assert_eq!("    hi", clformat!(nil, "~5,4@A", "hi"));
+        assert_eq!("   hi", clformat!(nil, "~4,,3@A", "hi"));
+        assert_eq!("toolong", clformat!(nil, "~4@A", "toolong"));
+    }
+
+    #[test]
+    fn aesthetic_max_columns() {
+        assert_eq!("zork", clformat!(nil, "~,,,'…,8A", "zork"));
+        assert_eq!("Frobozz…", clformat!(nil, "~,,,'…,8A", "Frobozz Magic"));
+        assert_eq!("Frobozz ", clformat!(nil, "~,,,,8A", "Frobozz Magic"));
+        assert_eq!("crème…", clformat!(nil, "~,,,'…,6A", "crème brûlée"));
+        assert_eq!("zork  |", clformat!(nil, "~6,,,,8A|", "zork"));
+        assert_eq!("..Fro.", clformat!(nil, "~6,,,'.,4@A", "Frobozz"));
+        assert_eq!("Frobozz", clformat!(nil, "~,,,,7A", "Frobozz"));
+        assert_eq!("|", clformat!(nil, "~,,,,0A|", "Frobozz"));
+        assert_eq!("\"Fr*", clformat!(nil, "~,,,'*,4S", "Frobozz"));
+        assert_eq!("Fro.", clformat!(nil, "~,,,V,VA", '.', 4, "Frobozz"));
+    }
+
+    #[test]
+    fn aesthetic_nil() {
+        assert_eq!("()", clformat!(nil, "~:A", Option::<i32>::None));
+        assert_eq!("42", clformat!(nil, "~:A", Some(42)));
+        assert_eq!("None", clformat!(nil, "~:A", "None"));
+        assert_eq!("()   |", clformat!(nil, "~5:A|", Option::<&str>::None));
+        assert_eq!("  zork", clformat!(nil, "~6:@A", Some("zork")));
+
+        let x = vec![Some(1), None, Some(3)];
+        assert_eq!("1 () 3", clformat!(nil, "~{~:A~^ ~}", x));
+    }
+
+    #[test]
+    fn aesthetic_lists() {
+        assert_eq!("(1 2 3)", clformat!(nil, "~:A", vec![1, 2, 3]));
+        assert_eq!("()", clformat!(nil, "~:A", Vec::<i32>::new()));
+        assert_eq!("(a b)", clformat!(nil, "~:A", ["a", "b"]));
+        assert_eq!(
+            "((1 2) () (3))",
+            clformat!(nil, "~:A", vec![vec![1, 2], vec![], vec![3]])
+        );
+        assert_eq!("(1 () 3)", clformat!(nil, "~:A", [Some(1), None, Some(3)]));
+        assert_eq!("(1 2)   |", clformat!(nil, "~8:A|", &[1, 2][..]));
+    }
+
+    #[test]
+    fn aesthetic_joined_lists() {
+        assert_eq!("", clformat!(nil, "~,,,,,',:A", Vec::<i32>::new()));
+        assert_eq!("1", clformat!(nil, "~,,,,,',:A", [1]));
+        assert_eq!("1, 2, 3", clformat!(nil, "~,,,,,',:A", vec![1, 2, 3]));
+        assert_eq!("a; b  |", clformat!(nil, "~6,,,,,';:A|", ["a", "b"]));
+        assert_eq!(
+            "(1 2)| ()",
+            clformat!(nil, "~,,,,,'|:A", [vec![1, 2], vec![]])
+        );
+        assert_eq!(
+            "1, 2, 3",
+            clformat!(nil, dyn_args = true, "~,,,,,',:A", [1, 2, 3])
+        );
+    }
+
+    /// A temperature that `Display`s verbosely in the alternate form.
+    struct Celsius(f64);
+
+    impl std::fmt::Display for Celsius {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            if f.alternate() {
+                write!(f, "{} degrees Celsius", self.0)
+            } else {
+                write!(f, "{}°C", self.0)
+            }
+        }
+    }
+
+    #[test]
+    fn aesthetic_alternate() {
+        assert_eq!("21.5°C", clformat!(nil, "~A", Celsius(21.5)));
+        assert_eq!("21.5 degrees Celsius", clformat!(nil, "~#A", Celsius(21.5)));
+        assert_eq!(
+            "21.5 degrees Celsius    |",
+            clformat!(nil, "~24#A|", Celsius(21.5))
+        );
+        assert_eq!(
+            "   21.5 degrees Celsius",
+            clformat!(nil, dyn_args = true, "~23@#A", Celsius(21.5))
+        );
+        assert_eq!(
+            "21.5 degrees Celsius",
+            clformat!(nil, "~:#A", Some(Celsius(21.5)))
+        );
+        // `~#,A` pads to the number of arguments remaining.
+        assert_eq!("21.5°C|", clformat!(nil, "~#,A|", Celsius(21.5)));
+    }
+
+    #[test]
+    fn smart_pointers() {
+        use std::fmt::Display;
+        use std::rc::Rc;
+
+        let boxed: Box<dyn Display> = Box::new("zork");
+        let name = String::from("nork");
+        let toes = Rc::new(1234);
+        assert_eq!(
+            "zork nork 1234",
+            clformat!(nil, "~A ~A ~A", boxed, &name, toes)
+        );
+        assert_eq!("1,234 -5", clformat!(nil, "~:D ~D", toes, Box::new(-5)));
+        assert_eq!("1234.00", clformat!(nil, "~,2F", Rc::new(1234.0)));
+        assert_eq!("yes", clformat!(nil, "~:[yes~;no~]", Rc::new(true)));
+
+        let list: Vec<Box<dyn Display>> = vec![Box::new(1), Box::new("zork")];
+        assert_eq!("1 zork", clformat!(nil, "~{~A~^ ~}", list));
+        let names = vec![name.as_str(), "plonk"];
+        assert_eq!("nork plonk", clformat!(nil, "~{~A~^ ~}", names));
+        let numbers = vec![Rc::new(1000), Rc::new(2000)];
+        assert_eq!("1,000 2,000", clformat!(nil, "~{~:D~^ ~}", numbers));
+        assert_eq!("zork 1234", clformat!(nil, "~*~A ~A", boxed, "zork", toes));
     }
 
     #[test]
@@ -34,6 +325,254 @@ mod tests {
         assert_eq!("ook, onk, nork, nonk", x);
     }
 
+    #[test]
+    fn iteration_borrows_the_list() {
+        let x = vec![String::from("ook"), String::from("onk")];
+        assert_eq!("ook, onk", clformat!(nil, "~{~A~^, ~}", x));
+        assert_eq!("ook onk", clformat!(nil, "~{~A~^ ~}", x));
+        assert_eq!(2, x.len());
+
+        let pairs = vec![(String::from("a"), 1), (String::from("b"), 2)];
+        assert_eq!("a=1 b=2 ", clformat!(nil, "~:{~A=~A ~}", pairs));
+        assert_eq!(2, pairs.len());
+
+        let range = 1..4;
+        assert_eq!("1 2 3 ", clformat!(nil, "~{~D ~}", range));
+        assert_eq!(1..4, range);
+    }
+
+    #[test]
+    fn iteration_over_references() {
+        let list = vec![String::from("ook"), String::from("onk")];
+        assert_eq!("ook onk ", clformat!(nil, "~{~A ~}", &list));
+        assert_eq!(2, list.len());
+
+        let array = [1, 2, 3];
+        assert_eq!("1 2 3 ", clformat!(nil, "~{~A ~}", &array));
+
+        let slice: &[i32] = &array[1..];
+        assert_eq!("2 3 ", clformat!(nil, "~{~A ~}", slice));
+        assert_eq!("1 2 ", clformat!(nil, "~{~A ~}", &array[..2]));
+        assert_eq!("2 3 ", clformat!(nil, dyn_args = true, "~{~A ~}", slice));
+        assert_eq!([1, 2, 3], array);
+    }
+
+    #[test]
+    fn iteration_empty_part() {
+        let toes: Vec<i32> = vec![];
+        assert_eq!("none", clformat!(nil, "~{~A~^, ~:;none~}", toes));
+        assert_eq!("1, 2", clformat!(nil, "~{~A~^, ~:;none~}", [1, 2]));
+        assert_eq!(
+            "none",
+            clformat!(nil, dyn_args = true, "~{~A~^, ~:;none~}", toes)
+        );
+
+        let map = std::collections::BTreeMap::<&str, i32>::new();
+        assert_eq!(
+            "no entries",
+            clformat!(nil, "~:{~A=~A ~:;no entries~}", map)
+        );
+
+        assert_eq!("none", clformat!(nil, "~@{~A~^ ~:;none~}"));
+        assert_eq!("a b", clformat!(nil, "~@{~A~^ ~:;none~}", "a", "b"));
+        assert_eq!("none", clformat!(nil, "~:@{~A~^ ~:;none~}"));
+
+        // The empty part takes arguments after the list, without consuming them.
+        assert_eq!(
+            "no toes|toes",
+            clformat!(nil, "~{~A~:;no ~A~}|~A", toes, "toes")
+        );
+
+        let nested = vec![vec![1, 2], vec![], vec![3]];
+        assert_eq!("12 - 3", clformat!(nil, "~{~{~A~:;-~}~^ ~}", nested));
+        assert_eq!("12 #1 3", clformat!(nil, "~{~{~A~:;#~#~}~^ ~}", nested));
+    }
+
+    #[test]
+    fn iteration_uses_successive_elements() {
+        let x = vec!["a", "1", "b", "2"];
+        assert_eq!("a-1, b-2", clformat!(nil, "~{~A-~A~^, ~}", x));
+    }
+
+    #[test]
+    fn iteration_breaks_between_elements() {
+        let x = vec![1, 2, 3, 4, 5];
+        assert_eq!("1-2, 3-4, 5", clformat!(nil, "~{~A~^-~A~^, ~}", &x));
+        assert_eq!("(1 2)(3 4)(5", clformat!(nil, "~{(~A~^ ~A)~}", &x));
+    }
+
+    #[test]
+    #[should_panic(expected = "clformat: the list ran out of elements during a pass of `~{`")]
+    fn iteration_runs_out_of_elements() {
+        clformat!(nil, "~{~A-~A~^, ~}", vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn sublist_iteration() {
+        let x: Vec<(&str, i32)> = vec![("a", 1), ("b", 2)];
+        assert_eq!("a=1 b=2 ", clformat!(nil, "~:{~A=~A ~}", &x));
+        assert_eq!("1a 2b", clformat!(nil, "~:{~*~A~2:*~A~^ ~}", &x));
+        assert_eq!("a=1, b=2", clformat!(nil, "~:{~A=~A~^, ~}", x));
+    }
+
+    #[test]
+    fn sublist_iteration_over_maps() {
+        use std::collections::BTreeMap;
+
+        let toes = BTreeMap::from([("zork", 10), ("nork", 8), ("ook", 12)]);
+        assert_eq!(
+            "nork → 8, ook → 12, zork → 10",
+            clformat!(nil, "~:{~A → ~A~^, ~}", toes)
+        );
+        assert_eq!("nork=8 ook=12 ", clformat!(nil, "~2:{~A=~A ~}", &toes));
+        assert_eq!(
+            "nork=8 ook=12 zork=10 ",
+            clformat!(nil, dyn_args = true, "~:{~A=~D ~}", toes.iter())
+        );
+        assert_eq!(3, toes.len());
+    }
+
+    #[test]
+    fn repeated_iteration() {
+        assert_eq!("xxx", clformat!(nil, "~{x~}", clformat::Times(3)));
+        assert_eq!("", clformat!(nil, "~{x~}", clformat::Times(0)));
+        assert_eq!("0, 1, 2", clformat!(nil, "~{~D~^, ~}", clformat::Times(3)));
+        let times = clformat::Times(2);
+        assert_eq!("zork zork ", clformat!(nil, "~{~*zork ~}", times));
+        assert_eq!("zork ", clformat!(nil, "~1{zork ~}", times));
+    }
+
+    #[test]
+    fn remaining_iteration() {
+        assert_eq!("1 2 3 ", clformat!(nil, "~@{~A ~}", 1, 2, 3));
+        assert_eq!(
+            "Toes: 1, 2, 3",
+            clformat!(nil, "Toes: ~@{~A~^, ~}", 1, 2, 3)
+        );
+        assert_eq!(
+            "zork: a=1, b=2",
+            clformat!(nil, "~A: ~@{~A=~A~^, ~}", "zork", "a", 1, "b", 2)
+        );
+        assert_eq!(
+            "Total 3: 1,000 2,000 3,000",
+            clformat!(nil, "Total ~D: ~@{~:D~^ ~}", 3, 1000, 2000, 3000)
+        );
+        assert_eq!("1, 2", clformat!(nil, "~@{~A~0[~^, ~]~}", 1, 2));
+    }
+
+    #[test]
+    fn remaining_sublist_iteration() {
+        assert_eq!(
+            "a=1 b=2 ",
+            clformat!(nil, "~:@{~A=~A ~}", ("a", 1), ("b", 2))
+        );
+        assert_eq!(
+            "zork: a=1, b=2",
+            clformat!(nil, "~A: ~:@{~A=~A~^, ~}", "zork", ("a", 1), ("b", 2))
+        );
+        assert_eq!(
+            "a=1, b=2",
+            clformat!(nil, "~:@{~A=~A~0[~^, ~]~}", ("a", 1), ("b", 2))
+        );
+        assert_eq!("", clformat!(nil, "~:@{~A=~A ~}"));
+        assert_eq!("zork ", clformat!(nil, "~A ~:@{~A=~A ~}", "zork"));
+    }
+
+    #[test]
+    fn outer_break() {
+        let x = vec![vec![1, 2], vec![3, 4]];
+        assert_eq!("1 2 ; 3", clformat!(nil, "~{~{~A~:^ ~}; ~}", &x));
+        assert_eq!("1 2; 3 ", clformat!(nil, "~{~{~A~^ ~:^~}; ~}", &x));
+        // The inner iteration doesn't hide whether the outer one has elements left.
+        assert_eq!("12; 34", clformat!(nil, "~{~{~A~}~^; ~}", &x));
+        assert_eq!("(1 2) (3 4)", clformat!(nil, "~{(~{~A~^ ~})~^ ~}", &x));
+
+        let x = vec![("a", 1), ("b", 2)];
+        assert_eq!("a=1, b=2", clformat!(nil, "~:{~A=~A~:^, ~}", x));
+    }
+
+    #[test]
+    fn break_with_parameters() {
+        assert_eq!(
+            "10 20 ",
+            clformat!(nil, "~{~V^~A ~}", vec![1, 10, 2, 20, 0, 30])
+        );
+        assert_eq!("1", clformat!(nil, "~{~A~1,1^ ~}", vec![1, 2]));
+        assert_eq!("1, 2", clformat!(nil, "~@{~A~#,1^, ~}", 1, 2, 3));
+        assert_eq!(
+            "3, 7, 12",
+            clformat!(nil, "~{~A~0,#,1^, ~}", vec![3, 7, 12, 5])
+        );
+        assert_eq!(
+            "100 200 ",
+            clformat!(nil, "~{~10,V,99^~A ~}", vec![1, 100, 2, 200, 50, 500])
+        );
+    }
+
+    #[test]
+    fn iteration_max_passes() {
+        assert_eq!("1 2 ", clformat!(nil, "~2{~A ~}", vec![1, 2, 3, 4]));
+        assert_eq!("1, 2", clformat!(nil, "~2{~A~^, ~}", vec![1, 2]));
+        assert_eq!("1, 2, 3", clformat!(nil, "~10{~A~^, ~}", vec![1, 2, 3]));
+        assert_eq!("", clformat!(nil, "~0{~A~}", vec![1, 2, 3]));
+        assert_eq!(
+            "a=1 ",
+            clformat!(nil, "~1:{~A=~A ~}", vec![("a", 1), ("b", 2)])
+        );
+    }
+
+    #[test]
+    fn iteration_loop_index() {
+        assert_eq!("0: a\n1: b\n", clformat!(nil, "~{~#: ~A~%~}", ["a", "b"]));
+        assert_eq!(
+            "0=a 1=b ",
+            clformat!(nil, "~:{~#~:[=~A~;-~] ~}", [(true, "a"), (true, "b")])
+        );
+        assert_eq!("0a 1b", clformat!(nil, "~@{~#~A~^ ~}", "a", "b"));
+        assert_eq!("0(1) 1(1)", clformat!(nil, "~:@{~#(~A)~^ ~}", (1,), (1,)));
+        // Each level of nesting has its own index.
+        assert_eq!(
+            "0[0a 1b] 1[0c] ",
+            clformat!(nil, "~{~#[~{~#~A~^ ~}] ~}", [vec!["a", "b"], vec!["c"]])
+        );
+        assert_eq!("0 1 2 ", clformat!(nil, "~{~}", "~# ", ["a", "b", "c"]));
+    }
+
+    #[test]
+    fn iteration_body_from_argument() {
+        assert_eq!("1 2 3 ", clformat!(nil, "~{~}", "~A ", vec![1, 2, 3]));
+        let body = String::from("~A~^, ");
+        assert_eq!("1, 2, ", clformat!(nil, "~2{~}", body, [1, 2, 3]));
+        assert_eq!("", clformat!(nil, "~{~}", "~A ", Vec::<i32>::new()));
+    }
+
+    #[test]
+    fn iteration_body_from_argument_error() {
+        let result = try_clformat!(nil, "~{~}", "~Q", vec![1, 2, 3]);
+        assert_eq!(Err(std::fmt::Error), result);
+
+        let mut bytes: Vec<u8> = Vec::new();
+        let result = try_clformat!(io!(bytes), "~{~}", "~Q", vec![1, 2, 3]);
+        let err = result.expect_err("the body is invalid");
+        let runtime = err
+            .get_ref()
+            .and_then(|err| err.downcast_ref::<clformat::FormatError>());
+        assert!(matches!(runtime, Some(clformat::FormatError::Parse(_))));
+    }
+
+    #[test]
+    #[should_panic(expected = "clformat: the body of `~{~}` failed for format string \"~{~}\"")]
+    fn iteration_body_from_argument_panic_message() {
+        clformat!(nil, "~{~}", "~Q", vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn iteration_without_arguments_terminates() {
+        assert_eq!("xxx", clformat!(nil, "~{x~}", vec![1, 2, 3]));
+        assert_eq!("", clformat!(nil, "~{~}", "", vec![1, 2, 3]));
+        assert_eq!("xx", clformat!(nil, "~@{x~}", 1, 2));
+    }
+
     #[test]
     fn skip_iteration() {
         let x = vec!["ook", "onk", "nork", "nonk"];
@@ -42,6 +581,96 @@ mod tests {
         assert_eq!("ook, nork", x);
     }
 
+    #[test]
+    fn backup() {
+        assert_eq!("xx", clformat!(nil, "~A~:*~A", "x"));
+        assert_eq!("x y x y", clformat!(nil, "~A ~A ~2:*~A ~A", "x", "y"));
+        assert_eq!("1 1 2", clformat!(nil, "~D ~:*~D ~D", 1, 2));
+    }
+
+    #[test]
+    fn skip() {
+        let (a, b, c, d) = ("a", "b", "c", "d");
+        assert_eq!("ad", clformat!(nil, "~A~2*~A", a, b, c, d));
+        assert_eq!("bc", clformat!(nil, "~*~A~0*~A", a, b, c));
+
+        let x = vec!["ook", "onk", "nork", "nonk", "zork", "zonk"];
+        assert_eq!("ook, nonk", clformat!(nil, "~{~A~2*~^, ~}", x));
+    }
+
+    #[test]
+    #[should_panic(expected = "skipped arguments are evaluated")]
+    fn skip_evaluates() {
+        fn skipped() -> &'static str {
+            panic!("skipped arguments are evaluated");
+        }
+
+        // As in Lisp, the arguments are evaluated even if they are skipped.
+        clformat!(nil, "~*~A", skipped(), "zork");
+    }
+
+    #[test]
+    fn goto() {
+        let (a, b, c) = ("a", "b", "c");
+        assert_eq!("c", clformat!(nil, "~2@*~A", a, b, c));
+        assert_eq!("bab", clformat!(nil, "~1@*~A~@*~A~A", a, b));
+
+        // Jump backward and then forward again.
+        assert_eq!("cac", clformat!(nil, "~2@*~A~0@*~A~2@*~A", a, b, c));
+    }
+
+    #[test]
+    fn tildes() {
+        assert_eq!("~ ~~~", clformat!(nil, "~~ ~3~"));
+        assert_eq!("~}~}", clformat!(nil, "~{~~}~}", [1, 2]));
+        assert_eq!("~1~ ~2~", clformat!(nil, "~{~~~A~~~^ ~}", [1, 2]));
+        assert_eq!("~]", clformat!(nil, "~:[~~]~;no~]", true));
+        assert_eq!("~;", clformat!(nil, "~[~~;~;no~]", 0));
+        assert_eq!("  ~>", clformat!(nil, "~4:<~~>~>"));
+    }
+
+    #[test]
+    fn argument_navigation() {
+        let (a, b, c) = ("a", "b", "c");
+        // Forward.
+        assert_eq!("b", clformat!(nil, "~*~A~*", a, b, c));
+        assert_eq!("c", clformat!(nil, "~2*~A", a, b, c));
+        assert_eq!("ab", clformat!(nil, "~0*~A~A", a, b));
+        // Backward.
+        assert_eq!("aa", clformat!(nil, "~A~:*~A", a));
+        assert_eq!("aba", clformat!(nil, "~A~A~2:*~A~*", a, b));
+        assert_eq!("ab", clformat!(nil, "~A~0:*~A", a, b));
+        // Absolute.
+        assert_eq!("ab", clformat!(nil, "~@*~A~A", a, b));
+        assert_eq!("ca", clformat!(nil, "~2@*~A~0@*~A~*", a, b, c));
+        assert_eq!("c", clformat!(nil, "~3@*~:*~A", a, b, c));
+        // Combined.
+        assert_eq!("cbab", clformat!(nil, "~2*~A~2:*~A~@*~A~A", a, b, c));
+    }
+
+    #[test]
+    fn evaluation_order() {
+        let count = std::cell::Cell::new(0);
+        let next = || {
+            count.set(count.get() + 1);
+            count.get()
+        };
+
+        // The arguments are evaluated in order before the directives jump among them.
+        assert_eq!(
+            "3 1 2",
+            clformat!(nil, "~2@*~A ~0@*~A ~A", next(), next(), next())
+        );
+
+        // Backing up uses the value again rather than evaluating the argument again.
+        assert_eq!("4 4 5", clformat!(nil, "~A ~:*~A ~A", next(), next()));
+        assert_eq!(5, count.get());
+
+        // Temporaries live until the output has been written.
+        let name = "zork";
+        assert_eq!("ZORK", clformat!(nil, "~A", name.to_uppercase().as_str()));
+    }
+
     #[test]
     fn decimals() {
         let num = 4200_isize;
@@ -51,12 +680,337 @@ mod tests {
         let num = -4200_isize;
         assert_eq!("-4,200", clformat!(nil, "~:D", num));
         assert_eq!("____-4,200", clformat!(nil, "~10,'_:D", num));
+        assert_eq!("-4,200", clformat!(nil, "~5:D", num));
+        assert_eq!("-4,200", clformat!(nil, "~6:D", num));
+        assert_eq!(" -4,200", clformat!(nil, "~7:D", num));
 
         let num = 4200_usize;
         assert_eq!("4200", clformat!(nil, "~D", num));
         assert_eq!("4,200", clformat!(nil, "~:D", num));
     }
 
+    #[test]
+    fn decimal_zero_padding() {
+        assert_eq!("-00042", clformat!(nil, "~6,'0D", -42));
+        assert_eq!("+00042", clformat!(nil, "~6,'0@D", 42));
+        assert_eq!("-01,234", clformat!(nil, "~7,'0:D", -1234));
+        assert_eq!("   -42", clformat!(nil, "~6D", -42));
+    }
+
+    #[test]
+    fn decimal_rounds_floats() {
+        assert_eq!("4", clformat!(nil, "~D", 3.7));
+        assert_eq!("-3", clformat!(nil, "~D", -3.2));
+        assert_eq!("1,235", clformat!(nil, "~:D", 1234.5_f32));
+        assert_eq!("0", clformat!(nil, "~D", 0.49999999999999994));
+    }
+
+    #[test]
+    fn decimal_prints_floats_that_are_not_finite() {
+        assert_eq!("NaN", clformat!(nil, "~D", f64::NAN));
+        assert_eq!("  inf", clformat!(nil, "~5D", f64::INFINITY));
+        assert_eq!("-inf", clformat!(nil, "~:D", f32::NEG_INFINITY));
+        assert_eq!("inf", clformat!(nil, "~@X", f64::INFINITY));
+    }
+
+    #[test]
+    fn params_from_args() {
+        assert_eq!("   42", clformat!(nil, "~VD", 5, 42));
+        assert_eq!("****42", clformat!(nil, "~V,'*D", 6, 42));
+        assert_eq!("---42", clformat!(nil, "~5,VD", '-', 42));
+
+        // Parameters are taken from the arguments before the argument they format.
+        let width = 4;
+        assert_eq!("  42 zork", clformat!(nil, "~VD ~A", width, 42, "zork"));
+        assert_eq!(
+            "1.50 ____zork",
+            clformat!(nil, "~,VF ~V,0,0,V:<~A~>", 2, 1.5, 8, '_', "zork")
+        );
+    }
+
+    #[test]
+    fn negative_params_from_args() {
+        // As in Common Lisp, a negative width is no width at all.
+        assert_eq!("x|", clformat!(nil, "~VA|", -1i32, "x"));
+        assert_eq!("42|", clformat!(nil, "~VD|", i64::MIN, 42));
+        assert_eq!("|", clformat!(nil, "~V%|", -3));
+        // A negative selector selects the default, as the argument to `~[` does.
+        assert_eq!("other", clformat!(nil, "~V[zero~;one~:;other~]", -1));
+    }
+
+    #[test]
+    fn params_arg_count() {
+        // `#` counts the arguments remaining, including the one being formatted.
+        assert_eq!("007|a|b", clformat!(nil, "~#,'0D|~A|~A", 7, "a", "b"));
+        assert_eq!(" 1|2", clformat!(nil, "~#D|~#D", 1, 2));
+
+        // Inside an iteration `#` counts the elements remaining.
+        let x = vec![1, 2, 3];
+        assert_eq!("  1, 2,3", clformat!(nil, "~{~#D~^,~}", x));
+    }
+
+    #[test]
+    fn decimal_grouping() {
+        assert_eq!("1,234,567", clformat!(nil, "~:D", 1234567));
+        assert_eq!("1,23,45,67", clformat!(nil, "~,,,2:D", 1234567));
+        assert_eq!("-1_234_567", clformat!(nil, "~,,'_:D", -1234567));
+    }
+
+    #[test]
+    fn decimal_grouping_needs_colon() {
+        assert_eq!("1234567", clformat!(nil, "~,,,2D", 1234567));
+        assert_eq!("1234567", clformat!(nil, "~,,'_,2D", 1234567));
+        assert_eq!("1234567", clformat!(nil, "~,,,3,2D", 1234567));
+        assert_eq!("   1234567", clformat!(nil, "~10,,,2D", 1234567));
+        assert_eq!("1234567", clformat!(nil, "~,,,VD", 2, 1234567));
+        assert_eq!("1,234", clformat!(nil, "~:D", 1234));
+    }
+
+    const GERMAN: clformat::Locale = clformat::Locale {
+        thousands: '.',
+        decimal: ',',
+        ..clformat::Locale::DEFAULT
+    };
+
+    #[test]
+    fn locale() {
+        assert_eq!("1.234.567", clformat!(nil, locale = GERMAN, "~:D", 1234567));
+        assert_eq!("1234,50", clformat!(nil, locale = GERMAN, "~,2F", 1234.5));
+        assert_eq!(
+            "Summe: 1.234 zu je 2,5",
+            clformat!(nil, locale = GERMAN, "Summe: ~:D zu je ~F", 1234, 2.5)
+        );
+        assert_eq!(
+            "1.234 1.234",
+            clformat!(nil, locale = GERMAN, "~{~:D~^ ~}", [1234, 1234])
+        );
+        assert_eq!(
+            "1,234,567",
+            clformat_args!(locale = clformat::Locale::DEFAULT, "~:D", 1234567).to_string()
+        );
+    }
+
+    #[test]
+    fn locale_is_overridden_by_parameters() {
+        assert_eq!(
+            "1,234,567",
+            clformat!(nil, locale = GERMAN, "~,,',:D", 1234567)
+        );
+        assert_eq!(
+            "1.23.45.67",
+            clformat!(nil, locale = GERMAN, "~,,,2:D", 1234567)
+        );
+
+        let indian = clformat::Locale {
+            grouping: 2,
+            ..clformat::Locale::DEFAULT
+        };
+        assert_eq!(
+            "1,23,45,67",
+            clformat!(nil, locale = indian, "~:D", 1234567)
+        );
+        assert_eq!(
+            "12,34,567",
+            clformat!(nil, locale = indian, "~,,,3,2:D", 1234567)
+        );
+        assert_eq!("zork", clformat!(nil, locale = indian, "~A", "zork"));
+    }
+
+    #[test]
+    fn locale_numerals() {
+        let arabic = clformat::Locale {
+            thousands: '٬',
+            decimal: '٫',
+            grouping: 3,
+            zero: '٠',
+        };
+        assert_eq!("١٢٣", clformat!(nil, locale = arabic, "~D", 123));
+        assert_eq!(
+            "١٬٢٣٤٬٥٦٧ ٢٫٥",
+            clformat!(nil, locale = arabic, "~:D ~F", 1234567, 2.5)
+        );
+        assert_eq!("  ١٢٣", clformat!(nil, locale = arabic, "~5D", 123));
+        assert_eq!("FF", clformat!(nil, locale = arabic, "~X", 255));
+    }
+
+    #[test]
+    fn dyn_args() {
+        let toes = vec![1, 2];
+        assert_eq!(
+            clformat!(
+                nil,
+                "~A ~S ~6@A|~:A ~W ~{~A~}",
+                "zork",
+                "zork",
+                42,
+                None::<i32>,
+                toes,
+                toes
+            ),
+            clformat!(
+                nil,
+                dyn_args = true,
+                "~A ~S ~6@A|~:A ~W ~{~A~}",
+                "zork",
+                "zork",
+                42,
+                None::<i32>,
+                toes,
+                toes
+            )
+        );
+        assert_eq!(
+            "1.234 zork",
+            clformat!(
+                nil,
+                dyn_args = true,
+                locale = GERMAN,
+                "~:D ~A",
+                1234,
+                "zork"
+            )
+        );
+    }
+
+    clformat_compile!(entry, "~A: ~:D");
+
+    #[test]
+    fn compiled_format() {
+        assert_eq!("toes: 1,000", entry!(nil, "toes", 1000));
+        assert_eq!("fingers: 10", entry!(nil, "fingers", 10,));
+
+        let mut output = String::new();
+        entry!(output, "zork", 2);
+        assert_eq!("zork: 2", output);
+
+        clformat_compile!(list, "~{~A~^, ~}");
+        let toes = vec![1, 2, 3];
+        assert_eq!("1, 2, 3", list!(nil, toes));
+        assert_eq!(3, toes.len());
+
+        clformat_compile!(german, locale = GERMAN, "~:D");
+        assert_eq!("1.234", german!(nil, 1234));
+
+        let mut bytes = Vec::new();
+        entry!(io!(bytes), "nork", 3);
+        assert_eq!(b"nork: 3", bytes.as_slice());
+    }
+
+    #[test]
+    fn writeln() {
+        assert_eq!("zork 42\n", clformat_writeln!(nil, "~A ~D", "zork", 42));
+        assert_eq!("\n", clformat_writeln!(nil, ""));
+
+        let mut output = String::new();
+        clformat_writeln!(output, "~{~A~^, ~}", [1, 2]);
+        clformat_writeln!(output, "~A", "zork");
+        assert_eq!("1, 2\nzork\n", output);
+
+        let mut bytes = Vec::new();
+        clformat_writeln!(io!(bytes), "~:D", 1234);
+        assert_eq!(b"1,234\n", bytes.as_slice());
+
+        clformat_writeln!(t, "~A", "zork");
+    }
+
+    #[test]
+    fn newlines() {
+        assert_eq!("a\nb", clformat!(nil, "a~%b"));
+        assert_eq!("a\n\n\nb", clformat!(nil, "a~3%b"));
+        assert_eq!("ab", clformat!(nil, "a~0%b"));
+        assert_eq!("a\n\nb", clformat!(nil, "a~V%~A", 2, "b"));
+    }
+
+    #[test]
+    fn crlf_newlines() {
+        assert_eq!("a\r\nb", clformat!(nil, "a~:%b"));
+        assert_eq!("a\r\n\r\n\r\nb", clformat!(nil, "a~3:%b"));
+        assert_eq!("a\r\n\r\nb", clformat!(nil, "a~V:%b", 2));
+        assert_eq!("a\nb\r\n", clformat!(nil, "a~%b~:%"));
+    }
+
+    #[test]
+    fn radix() {
+        assert_eq!("FF", clformat!(nil, "~X", 255));
+        assert_eq!("377", clformat!(nil, "~O", 255));
+        assert_eq!("11111111", clformat!(nil, "~B", 255));
+        assert_eq!("-FF", clformat!(nil, "~X", -255));
+        assert_eq!("0000FFFF", clformat!(nil, "~8,'0X", 0xffff));
+        assert_eq!("1111 1111", clformat!(nil, "~,,' ,4:B", 255));
+    }
+
+    #[test]
+    fn radix_lowercase() {
+        assert_eq!("ff", clformat!(nil, "~x", 255));
+        assert_eq!("FF", clformat!(nil, "~X", 255));
+        assert_eq!("0000ffff", clformat!(nil, "~8,'0x", 0xffff));
+        assert_eq!("0xff", clformat!(nil, "~@x", 255));
+        assert_eq!("0xff", clformat!(nil, "~@X", 255));
+
+        let n = 0xbeef;
+        assert_eq!("beef BEEF", clformat!(nil, "~`n`x ~`n`X"));
+        assert_eq!(
+            "be,ef BE,EF",
+            clformat!(nil, dyn_args = true, "~,,,2:x ~,,,2:X", n, n)
+        );
+    }
+
+    #[test]
+    fn pad_after_sign() {
+        let num = -4200_isize;
+        assert_eq!("____-4,200", clformat!(nil, "~10,'_:D", num));
+        assert_eq!("-____4,200", clformat!(nil, "~10,'_,,,,1:D", num));
+        assert_eq!("+    42", clformat!(nil, "~7,,,,,1@D", 42));
+        assert_eq!("     42", clformat!(nil, "~7,,,,,1D", 42));
+        // Directives ignore case, so `~d` pads before the sign as `~D` does.
+        assert_eq!("  -42|  -42", clformat!(nil, "~5d|~5D", -42, -42));
+    }
+
+    #[test]
+    fn radix_prefix() {
+        assert_eq!("0xff", clformat!(nil, "~@X", 255));
+        assert_eq!("0o377", clformat!(nil, "~@O", 255));
+        assert_eq!("0b11111111", clformat!(nil, "~@B", 255));
+        assert_eq!("-0xff", clformat!(nil, "~@X", -255));
+        assert_eq!("0x00ff", clformat!(nil, "~6,'0@X", 255));
+    }
+
+    #[test]
+    fn decimal_secondary_grouping() {
+        assert_eq!("12,34,567", clformat!(nil, "~,,,3,2:D", 1234567));
+        assert_eq!("12,34,56,789", clformat!(nil, "~,,,3,2:D", 123456789));
+        assert_eq!("  12,34,567", clformat!(nil, "~11,,,,2:D", 1234567));
+        assert_eq!("12,34,567", clformat!(nil, "~,,,3,V:D", 2, 1234567));
+        assert_eq!("  1234,567", clformat!(nil, "~10,,,3,V:D", 0, 1234567));
+    }
+
+    #[test]
+    fn large_decimals() {
+        assert_eq!(u64::MAX.to_string(), clformat!(nil, "~D", u64::MAX));
+        assert_eq!(i128::MIN.to_string(), clformat!(nil, "~D", i128::MIN));
+        assert_eq!(u128::MAX.to_string(), clformat!(nil, "~D", u128::MAX));
+        assert_eq!("0", clformat!(nil, "~D", 0));
+    }
+
+    #[test]
+    fn big_decimals() {
+        use num_bigint::BigInt;
+
+        let big = "-1234567890123456789012345678901234567890"
+            .parse::<BigInt>()
+            .unwrap();
+        assert_eq!(
+            "-1,234,567,890,123,456,789,012,345,678,901,234,567,890",
+            clformat!(nil, "~:D", big)
+        );
+        assert_eq!(big.to_string(), clformat!(nil, "~D", big));
+        assert_eq!(
+            "-1234_5678_9012_3456_7890_1234_5678_9012_3456_7890",
+            clformat!(nil, "~,,'_,4:D", big)
+        );
+        assert_eq!(format!("-{:X}", big.magnitude()), clformat!(nil, "~X", big));
+    }
+
     #[test]
     fn floats() {
         let num = std::f64::consts::PI;
@@ -68,6 +1022,79 @@ mod tests {
         assert_eq!("3.50", clformat!(nil, "~,2F", num));
     }
 
+    #[test]
+    fn floats_without_decimal_places() {
+        assert_eq!("3.5", clformat!(nil, "~F", 3.5));
+        assert_eq!("100.0", clformat!(nil, "~F", 100.0));
+        assert_eq!("-2.0", clformat!(nil, "~F", -2.0_f32));
+        assert_eq!("0.000001", clformat!(nil, "~F", 0.000001));
+        assert_eq!("  100.0", clformat!(nil, "~7F", 100.0));
+        assert_eq!("**100.0", clformat!(nil, "~7,,,,,'*F", 100.0));
+        assert_eq!("4", clformat!(nil, "~,0F", 3.5));
+    }
+
+    #[test]
+    fn floats_negative_zero() {
+        assert_eq!("0.00", clformat!(nil, "~,2F", -0.0));
+        assert_eq!("0.00", clformat!(nil, "~,2F", 0.0));
+        assert_eq!("0.00", clformat!(nil, "~,2F", -0.001));
+        assert_eq!(" 0.00", clformat!(nil, "~5,2F", -0.001));
+        assert_eq!("0.0", clformat!(nil, "~F", -0.0));
+        assert_eq!("-0.01", clformat!(nil, "~,2F", -0.009));
+    }
+
+    #[test]
+    fn floats_without_leading_zero() {
+        assert_eq!(".50", clformat!(nil, "~,2:F", 0.5));
+        assert_eq!("-.50", clformat!(nil, "~,2:F", -0.5));
+        assert_eq!("+.50", clformat!(nil, "~,2:@F", 0.5));
+        assert_eq!("1.50", clformat!(nil, "~,2:F", 1.5));
+        assert_eq!("10.25", clformat!(nil, "~,2:F", 10.25));
+        assert_eq!("  .50", clformat!(nil, "~5,2:F", 0.5));
+    }
+
+    #[test]
+    fn floats_justified() {
+        let num = std::f64::consts::PI;
+        assert_eq!("      3.14|", clformat!(nil, "~10,2F|", num));
+        assert_eq!("3.14      |", clformat!(nil, "~10,2#F|", num));
+        assert_eq!("+3.14-----|", clformat!(nil, "~10,2,,,,'-@#F|", num));
+        assert_eq!("-3.1416   |", clformat!(nil, "~10,4#F|", -num));
+        // `~#,F` takes its width from the number of arguments remaining instead.
+        assert_eq!("3.1|a|b", clformat!(nil, "~#,1F|~A|~A", num, "a", "b"));
+        assert_eq!(
+            " 3.1|a|b|c",
+            clformat!(nil, "~#,1F|~A|~A|~A", num, "a", "b", "c")
+        );
+        // A number wider than the field overflows it on either side.
+        assert_eq!("31415.9|", clformat!(nil, "~3,1#F|", num * 10_000.0));
+    }
+
+    #[test]
+    fn floats_round_half_up() {
+        assert_eq!("2.67 2.68", clformat!(nil, "~,2F ~,2,,,,,1F", 2.675, 2.675));
+        assert_eq!(
+            "2 3 -3",
+            clformat!(nil, "~,0F ~,0,,,,,1F ~,0,,,,,1F", 2.5, 2.5, -2.5)
+        );
+        assert_eq!("  0.50", clformat!(nil, "~6,2,,,,,1F", 0.5));
+        assert_eq!("2,68", clformat!(nil, locale = GERMAN, "~,2,,,,,1F", 2.675));
+        // Directives ignore case, so `~f` rounds as `~F` does.
+        assert_eq!("2.67", clformat!(nil, "~,2f", 2.675));
+    }
+
+    #[test]
+    fn floats_with_sign() {
+        assert_eq!("+3.50", clformat!(nil, "~,2@F", 3.5));
+        assert_eq!("-3.50", clformat!(nil, "~,2@F", -3.5));
+        assert_eq!("+0.00", clformat!(nil, "~,2@F", 0.0));
+        assert_eq!("-0.00", clformat!(nil, "~,2@F", -0.001));
+        assert_eq!(" +100.0", clformat!(nil, "~7@F", 100.0));
+        assert_eq!(" +3.50", clformat!(nil, "~6,2@F", 3.5));
+        assert_eq!("+3.50", clformat!(nil, "~5,2@F", 3.5));
+        assert_eq!("  3.50", clformat!(nil, "~6,2F", 3.5));
+    }
+
     #[test]
     fn alignment() {
         let text = "zogwobble";
@@ -78,9 +1105,188 @@ mod tests {
         assert_eq!("--zogwobble--", clformat!(nil, "~13,0,0,'-:@<~A~>", text));
     }
 
+    #[test]
+    fn alignment_min_pad() {
+        let text = "zogwobble";
+        // The text already fills the minimum columns, but two pad characters are required.
+        assert_eq!("zogwobble--", clformat!(nil, "~9,1,2,'-<~A~>", text));
+        assert_eq!("--zogwobble", clformat!(nil, "~5,1,2,'-:<~A~>", text));
+        assert_eq!("zogwobble    ", clformat!(nil, "~13,4,2<~A~>", text));
+    }
+
+    #[test]
+    fn alignment_col_inc() {
+        let text = "zogwobble";
+        // The field is widened four columns at a time until the text fits.
+        assert_eq!("zogwobble---", clformat!(nil, "~8,4,0,'-<~A~>", text));
+        assert_eq!("zogwobble-----", clformat!(nil, "~10,4,2,'-<~A~>", text));
+        assert_eq!("---zogwobble", clformat!(nil, "~12,4,0,'-:<~A~>", text));
+    }
+
+    #[test]
+    fn alignment_segments() {
+        assert_eq!("left      right", clformat!(nil, "~15<left~;right~>"));
+        assert_eq!("zork  2  nork", clformat!(nil, "~13<zork~;~A~;nork~>", 2));
+        // The extra padding goes in the first gaps.
+        assert_eq!("zork   2  nork", clformat!(nil, "~14<zork~;~A~;nork~>", 2));
+        assert_eq!("zork--nork", clformat!(nil, "~5,1,2,'-<zork~;nork~>"));
+    }
+
+    #[test]
+    fn alignment_segments_modifiers() {
+        assert_eq!("   zork   nork", clformat!(nil, "~14:<zork~;nork~>"));
+        assert_eq!("zork   nork   ", clformat!(nil, "~14@<zork~;nork~>"));
+        assert_eq!("  zork  nork  ", clformat!(nil, "~14:@<zork~;nork~>"));
+        assert_eq!("  zork  nork ", clformat!(nil, "~13:@<zork~;nork~>"));
+    }
+
+    #[test]
+    fn alignment_overflow() {
+        // The overflow clause is only written when the justified text won't fit on the line.
+        assert_eq!(
+            "zork nork",
+            clformat!(nil, "zork ~<~%;; ~,20:;~A~>", "nork")
+        );
+        assert_eq!(
+            "zork \n;; nork nork nork nork",
+            clformat!(nil, "zork ~<~%;; ~,20:;~A~>", "nork nork nork nork")
+        );
+        // The column is counted from the last newline, and must leave the spare columns.
+        assert_eq!(
+            "zork\nzork \n;; nork",
+            clformat!(nil, "zork~%zork ~<~%;; ~11,10:;~A~>", "nork")
+        );
+        assert_eq!(
+            "zork, nork, \n    plork",
+            clformat!(nil, "~{~<~%    ~,12:;~A~>~^, ~}", ["zork", "nork", "plork"])
+        );
+    }
+
+    #[test]
+    fn alignment_overflow_column() {
+        // Every directive before `~<` moves the column, and a newline resets it, so the line
+        // is 14 columns wide when the overflow clause is considered.
+        let list = ["a", "b"];
+        assert_eq!(
+            "zork\n1,234 2.5yy abnork",
+            clformat!(
+                nil,
+                "~A~%~:D ~,1F~:[x~;yy~] ~{~A~}~<~%!~,18:;~A~>",
+                "zork",
+                1234,
+                2.5,
+                false,
+                list,
+                "nork"
+            )
+        );
+        assert_eq!(
+            "zork\n1,234 2.5yy ab\n!nork",
+            clformat!(
+                nil,
+                "~A~%~:D ~,1F~:[x~;yy~] ~{~A~}~<~%!~,17:;~A~>",
+                "zork",
+                1234,
+                2.5,
+                false,
+                list,
+                "nork"
+            )
+        );
+    }
+
+    #[test]
+    fn alignment_unicode() {
+        let text = "zogwöbble";
+        assert_eq!("  zogwöbble  ", clformat!(nil, "~13:@<~A~>", text));
+        assert_eq!("zogwöbble----", clformat!(nil, "~13,,,'-A", text));
+    }
+
+    #[test]
+    fn alignment_counts_characters() {
+        // Accented characters take more than one byte, but pad as a single column.
+        let text = "crème brûlée";
+        assert_eq!(15, text.len());
+        assert_eq!(12, text.chars().count());
+        assert_eq!("crème brûlée  |", clformat!(nil, "~14A|", text));
+        assert_eq!("  crème brûlée", clformat!(nil, "~14:<~A~>", text));
+        assert_eq!(" \"crème brûlée\"", clformat!(nil, "~15@S", text));
+    }
+
+    #[test]
+    fn alignment_evaluates_once() {
+        let mut count = 0;
+        let mut next = || {
+            count += 1;
+            count
+        };
+        assert_eq!("    1", clformat!(nil, "~5:<~A~>", next()));
+        assert_eq!(1, count);
+    }
+
     #[derive(Debug)]
     struct Nork;
 
+    #[allow(dead_code)]
+    #[derive(Debug)]
+    struct Zork {
+        ook: usize,
+        onk: &'static str,
+    }
+
+    #[test]
+    fn standard_padding() {
+        // The quotes count towards the width.
+        assert_eq!("\"hi\"      ", clformat!(nil, "~10S", "hi"));
+        assert_eq!("      \"hi\"", clformat!(nil, "~10@S", "hi"));
+        assert_eq!("Nork------", clformat!(nil, "~10,,,'-S", Nork));
+
+        let zork = Zork { ook: 1, onk: "onk" };
+        assert_eq!(
+            "Zork { ook: 1, onk: \"onk\" }   |",
+            clformat!(nil, "~30S|", zork)
+        );
+    }
+
+    #[test]
+    fn standard_chars() {
+        assert_eq!("'a'", clformat!(nil, "~S", 'a'));
+        assert_eq!(r"'\''", clformat!(nil, "~S", '\''));
+        assert_eq!(r#"'"'"#, clformat!(nil, "~S", '"'));
+        assert_eq!(r"'\\'", clformat!(nil, "~S", '\\'));
+        assert_eq!(
+            r"'\n' '\t' '\0'",
+            clformat!(nil, "~S ~S ~S", '\n', '\t', '\0')
+        );
+        assert_eq!(r"'\u{7}'", clformat!(nil, "~S", '\u{7}'));
+        assert_eq!("'é'", clformat!(nil, "~S", 'é'));
+    }
+
+    #[test]
+    fn standard_strings() {
+        assert_eq!(r#""zork""#, clformat!(nil, "~S", "zork"));
+        assert_eq!(r#""""#, clformat!(nil, "~S", ""));
+        assert_eq!(r#""say \"hi\"""#, clformat!(nil, "~S", r#"say "hi""#));
+        assert_eq!(r#""it's""#, clformat!(nil, "~S", "it's"));
+        assert_eq!(r#""C:\\zork""#, clformat!(nil, "~S", r"C:\zork"));
+        assert_eq!(r#""a\nb\r\n\t\0""#, clformat!(nil, "~S", "a\nb\r\n\t\0"));
+        assert_eq!(r#""\u{1b}[0m""#, clformat!(nil, "~S", "\u{1b}[0m"));
+        assert_eq!(r#""crème""#, clformat!(nil, "~S", String::from("crème")));
+        assert_eq!(r#""a\nb""#, clformat!(nil, dyn_args = true, "~S", "a\nb"));
+        // The escapes count towards the width.
+        assert_eq!(r#""a\nb"  |"#, clformat!(nil, "~8S|", "a\nb"));
+    }
+
+    #[test]
+    fn standard_alternate() {
+        let zork = Zork { ook: 1, onk: "onk" };
+        assert_eq!(
+            "Zork {\n    ook: 1,\n    onk: \"onk\",\n}",
+            clformat!(nil, "~#S", zork)
+        );
+        assert_eq!("\"hi\"  |", clformat!(nil, "~6#S|", "hi"));
+    }
+
     #[test]
     fn alignment_debug() {
         // Output and align a debug output of a struct and a string.
@@ -116,6 +1322,117 @@ mod tests {
         assert_eq!("gork", clformat!(nil, "~[zork~;plork~;nork~:;gork~]", x));
     }
 
+    enum Status {
+        Running,
+        Stopped,
+        Unknown,
+    }
+
+    impl clformat::Choice for Status {
+        fn choice(&self) -> usize {
+            match self {
+                Status::Running => 0,
+                Status::Stopped => 1,
+                Status::Unknown => usize::MAX,
+            }
+        }
+    }
+
+    #[test]
+    fn conditional_table() {
+        assert_eq!(
+            "running",
+            clformat!(nil, "~[running~;stopped~:;unknown~]", Status::Running)
+        );
+        assert_eq!(
+            "stopped",
+            clformat!(nil, "~[running~;stopped~:;unknown~]", &Status::Stopped)
+        );
+        assert_eq!(
+            "unknown",
+            clformat!(nil, "~[running~;stopped~:;unknown~]", Status::Unknown)
+        );
+        assert_eq!(
+            "stopped",
+            clformat!(
+                nil,
+                dyn_args = true,
+                "~[running~;stopped~:;unknown~]",
+                Status::Stopped
+            )
+        );
+
+        // Integers of any type select by their value, and negative ones select the default.
+        assert_eq!(
+            "stopped",
+            clformat!(nil, "~[running~;stopped~:;unknown~]", 1u8)
+        );
+        assert_eq!(
+            "unknown",
+            clformat!(nil, "~[running~;stopped~:;unknown~]", -1i64)
+        );
+        assert_eq!(
+            "running",
+            clformat!(nil, "~[running~;stopped~:;unknown~]", Box::new(0usize))
+        );
+    }
+
+    #[test]
+    fn conditional_on_arg_count() {
+        assert_eq!("none", clformat!(nil, "~#[none~;one~:;many~]"));
+
+        assert_eq!(
+            "one: 1",
+            clformat!(nil, "~#[none~;one: ~A~:;many: ~@{~A~^, ~}~]", 1)
+        );
+        assert_eq!(
+            "many: 1, 2, 3",
+            clformat!(nil, "~#[none~;one: ~A~:;many: ~@{~A~^, ~}~]", 1, 2, 3)
+        );
+
+        assert_eq!(
+            "1, 2 and 3",
+            clformat!(nil, "~{~A~#[~; and ~:;, ~]~}", vec![1, 2, 3])
+        );
+    }
+
+    #[test]
+    fn conditional_selector() {
+        assert_eq!("b", clformat!(nil, "~1[a~;b~;c~]"));
+        assert_eq!("c 7", clformat!(nil, "~2[a~;b~;c ~A~]", 7));
+        assert_eq!("", clformat!(nil, "~2[a~;b~;~]"));
+
+        let choice = 2;
+        assert_eq!("c 7", clformat!(nil, "~V[a~;b~;c ~A~]", choice, 7));
+        assert_eq!("d", clformat!(nil, "~V[a~;b~;c~:;d~]", 9));
+    }
+
+    #[test]
+    fn arguments_are_borrowed() {
+        let name = String::from("Zork");
+        let toes = Some(vec![1, 2]);
+        let fish: Option<String> = Some(String::from("cod"));
+        let list = vec![String::from("a"), String::from("b")];
+
+        assert_eq!(
+            "Zork \"Zork\" toes: 1 2 cod a b ",
+            clformat!(
+                nil,
+                "~A ~:*~S~@[ toes: ~{~A ~}~]~@[~A ~]~{~A ~}",
+                name,
+                toes,
+                fish,
+                &list
+            )
+        );
+
+        // Everything can still be used after formatting.
+        assert_eq!(4, name.len());
+        assert_eq!(Some(vec![1, 2]), toes);
+        assert_eq!(Some("cod".to_string()), fish);
+        assert_eq!(2, list.len());
+    }
+
     #[test]
     fn conditional_consumes() {
         let fish = Some(2);
@@ -127,6 +1444,12 @@ mod tests {
         );
     }
 
+    #[test]
+    fn conditional_consumes_only_if_present() {
+        assert_eq!("value: 5", clformat!(nil, "~@[value: ~A~]", Some(5)));
+        assert_eq!("", clformat!(nil, "~@[value: ~A~]", None::<i32>));
+    }
+
     #[test]
     fn boolean_conditional() {
         let x = true;
@@ -135,4 +1458,61 @@ mod tests {
         let x = false;
         assert_eq!("zoggle", clformat!(nil, "~:[nork~;zoggle~]", x));
     }
+
+    #[test]
+    fn boolean_conditional_truthy() {
+        assert_eq!("some", clformat!(nil, "~:[some~;none~]", Some(3)));
+        assert_eq!("none", clformat!(nil, "~:[some~;none~]", None::<i32>));
+        assert_eq!("non-zero", clformat!(nil, "~:[non-zero~;zero~]", 7u8));
+        assert_eq!("zero", clformat!(nil, "~:[non-zero~;zero~]", 0));
+        assert_eq!("non-empty", clformat!(nil, "~:[non-empty~;empty~]", "zork"));
+        assert_eq!("empty", clformat!(nil, "~:[non-empty~;empty~]", ""));
+
+        let name = String::new();
+        assert_eq!("empty", clformat!(nil, "~:[non-empty~;empty~]", name));
+        assert_eq!("", name);
+    }
+
+    #[test]
+    fn boolean_conditional_custom_truthy() {
+        struct Toes(usize);
+
+        impl clformat::Truthy for Toes {
+            fn is_truthy(&self) -> bool {
+                self.0 >= 10
+            }
+        }
+
+        assert_eq!("all toes", clformat!(nil, "~:[all~;some~] toes", Toes(10)));
+        assert_eq!("some toes", clformat!(nil, "~:[all~;some~] toes", Toes(3)));
+    }
+
+    #[test]
+    fn captured() {
+        let name = "Zork";
+        let toes = 2000;
+        assert_eq!(
+            "Zork has  2,000 toes",
+            clformat!(nil, "~`name`A has ~`toes`6:D toes")
+        );
+        assert_eq!("Zork, Nork", clformat!(nil, "~`name`A, ~A", "Nork"));
+    }
+
+    #[test]
+    fn captured_field() {
+        struct Point {
+            x: i32,
+            y: (i32, i32),
+        }
+
+        let point = Point { x: 3, y: (4, 5) };
+        assert_eq!("3, 005", clformat!(nil, "~`point.x`A, ~`point.y.1`3,'0D"));
+    }
+
+    #[test]
+    fn write() {
+        let zork = vec![("toes", Some(2000)), ("fish", None)];
+        assert_eq!(format!("{:?}", zork), clformat!(nil, "~W", zork));
+        assert_eq!(format!("{:#?}", zork), clformat!(nil, "~:W", zork));
+    }
 }
@@ -0,0 +1,8 @@
+use clformat::clformat;
+
+fn main() {
+    let x = 1;
+    clformat!(nil, "~:*~A", x);
+    clformat!(nil, "~A ~3:*~A", x);
+    clformat!(nil, "~{~A~:*~}", [x]);
+}
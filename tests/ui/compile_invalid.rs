@@ -0,0 +1,8 @@
+use clformat::clformat_compile;
+
+clformat_compile!(entry, "~A ~Q");
+
+fn main() {
+    clformat_compile!(pair, "~A ~A");
+    pair!(nil, 1);
+}
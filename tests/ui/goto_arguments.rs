@@ -0,0 +1,7 @@
+use clformat::clformat;
+
+fn main() {
+    let x = 1;
+    clformat!(nil, "~A~2@*", x);
+    clformat!(nil, "~{~A~0@*~}", [x]);
+}
@@ -0,0 +1,6 @@
+use clformat::clformat;
+
+fn main() {
+    clformat!(nil, "Ook, ~z", "zork");
+    clformat!(nil, "~{~A~^, ~5z~}", vec![1, 2]);
+}
@@ -0,0 +1,6 @@
+use clformat::clformat;
+
+fn main() {
+    let mut stdout = std::io::stdout();
+    clformat!(stdout, "~A", "zork");
+}
@@ -0,0 +1,8 @@
+use clformat::clformat;
+
+fn main() {
+    clformat!(nil, "~{~@{~A~}~}", [1]);
+    clformat!(nil, "~{~A~:^~}", [1]);
+    clformat!(nil, "~@{~A~:^~}", 1, 2);
+    clformat!(nil, "~:@{~A~:^~}", (1,), (2,));
+}
@@ -0,0 +1,8 @@
+use clformat::clformat;
+
+struct Zork;
+
+fn main() {
+    clformat!(nil, "~A and ~A", 1, Zork);
+    clformat!(nil, "~5S", Zork);
+}
@@ -0,0 +1,5 @@
+use clformat::clformat;
+
+fn main() {
+    clformat!(nil, "~@{~A=~A ~}", "a", 1, "b");
+}
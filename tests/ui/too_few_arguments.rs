@@ -0,0 +1,7 @@
+use clformat::clformat;
+
+fn main() {
+    clformat!(nil, "~A, ~A and ~A", "zork", "nork");
+    clformat!(nil, "~V,'0D", 4);
+    clformat!(nil, "~A ~[~A~;~A ~A~]", 1, 0, "zork");
+}
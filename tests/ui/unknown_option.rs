@@ -0,0 +1,5 @@
+use clformat::clformat;
+
+fn main() {
+    clformat!(nil, dyn_arg = true, "~A", "zork");
+}
@@ -0,0 +1,7 @@
+use clformat::clformat;
+
+fn main() {
+    clformat!(nil, "~10<~A", "zork");
+    clformat!(nil, "~{~A~^, ", vec![1, 2]);
+    clformat!(nil, "~[zero~;one", 1);
+}
@@ -0,0 +1,7 @@
+use clformat::clformat;
+
+fn main() {
+    clformat!(nil, "~A", "zork", "nork");
+    clformat!(nil, "~{~A ~A~}", vec![1, 2], 3, 4);
+    clformat!(nil, "~10<~A~>", "zork", "nork");
+}
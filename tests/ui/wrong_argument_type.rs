@@ -0,0 +1,12 @@
+use clformat::clformat;
+
+struct Zork;
+
+fn main() {
+    clformat!(nil, "~D", Zork);
+    clformat!(nil, "~,2F", Zork);
+    clformat!(nil, "~:[yes~;no~]", Zork);
+    clformat!(nil, "~[yes~;no~]", Zork);
+    clformat!(nil, "~@[~A~]", Zork);
+    clformat!(nil, "~{~A~}", 5);
+}